@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use embed_str::EmbeddingStr;
+use std::hint::black_box;
+
+const SHORT: &str = "short str";
+const LONG: &str = "long string is longer than limit";
+
+fn bench_clone(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone");
+
+    let embedded = EmbeddingStr::from(SHORT);
+    group.bench_function("EmbeddingStr/embedded", |b| {
+        b.iter(|| black_box(&embedded).clone())
+    });
+    let boxed = EmbeddingStr::from(LONG);
+    group.bench_function("EmbeddingStr/boxed", |b| {
+        b.iter(|| black_box(&boxed).clone())
+    });
+
+    let short_string = String::from(SHORT);
+    group.bench_function("String/short", |b| {
+        b.iter(|| black_box(&short_string).clone())
+    });
+    let long_string = String::from(LONG);
+    group.bench_function("String/long", |b| {
+        b.iter(|| black_box(&long_string).clone())
+    });
+
+    let short_box: Box<str> = SHORT.into();
+    group.bench_function("Box<str>/short", |b| {
+        b.iter(|| black_box(&short_box).clone())
+    });
+    let long_box: Box<str> = LONG.into();
+    group.bench_function("Box<str>/long", |b| b.iter(|| black_box(&long_box).clone()));
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_clone);
+criterion_main!(benches);
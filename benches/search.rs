@@ -0,0 +1,28 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use embed_str::EmbeddingStr;
+use std::hint::black_box;
+
+// run with and without `--features memchr` to compare the memchr and scalar paths
+fn bench_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+
+    let content = format!("{}\n", "lorem ipsum dolor sit amet ".repeat(40)).repeat(25);
+    let s = EmbeddingStr::from(content.as_str());
+    group.bench_function("count_char/EmbeddingStr", |b| {
+        b.iter(|| black_box(&s).count_char('\n'))
+    });
+    group.bench_function("count_char/str::matches", |b| {
+        b.iter(|| black_box(content.as_str()).matches('\n').count())
+    });
+    let last = format!("{}!", content);
+    let s = EmbeddingStr::from(last.as_str());
+    group.bench_function("find/EmbeddingStr", |b| b.iter(|| black_box(&s).find('!')));
+    group.bench_function("find/str::find", |b| {
+        b.iter(|| black_box(last.as_str()).find('!'))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_search);
+criterion_main!(benches);
@@ -0,0 +1,261 @@
+//! Packed storage for many long strings
+
+use crate::EmbeddingStr;
+use std::cell::RefCell;
+use std::fmt;
+
+// capacity of a regular chunk; longer strings get a chunk of their own
+const CHUNK_SIZE: usize = 4096;
+
+/// Append-only store that packs long strings into shared chunks instead of boxing each one
+///
+/// Strings short enough to embed are kept inline as usual. Longer ones are copied into the
+/// current chunk, and a new chunk is started when it runs out of room, so loading a large
+/// word list takes a handful of allocations instead of one per word.
+///
+/// The arena owns all of the long string data. [`push`](Self::push) and
+/// [`get`](Self::get) take `&self` and return [`ArenaStr`] views borrowing from the arena, so
+/// the views stay valid while more strings are pushed but can't outlive the arena itself.
+/// Nothing is freed until the arena is dropped; use [`ArenaStr::to_embedding_str`] to keep a
+/// value around independently of it.
+#[derive(Default)]
+pub struct EmbeddingStrArena {
+    // a chunk never grows past its initial capacity, so its buffer never moves
+    chunks: RefCell<Vec<String>>,
+    entries: RefCell<Vec<Entry>>,
+}
+
+enum Entry {
+    Embedded(EmbeddingStr),
+    Packed {
+        chunk: usize,
+        start: usize,
+        len: usize,
+    },
+}
+
+impl EmbeddingStrArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a copy of `s`, returning a view of it.
+    pub fn push(&self, s: &str) -> ArenaStr<'_> {
+        if EmbeddingStr::will_embed_len(s.len()) {
+            let s = EmbeddingStr::from(s);
+            self.entries.borrow_mut().push(Entry::Embedded(s.clone()));
+            return ArenaStr(Repr::Embedded(s));
+        }
+
+        let mut chunks = self.chunks.borrow_mut();
+        let fits = chunks
+            .last()
+            .is_some_and(|chunk| chunk.capacity() - chunk.len() >= s.len());
+        if !fits {
+            chunks.push(String::with_capacity(s.len().max(CHUNK_SIZE)));
+        }
+        let chunk = chunks.len() - 1;
+        let start = chunks[chunk].len();
+        chunks[chunk].push_str(s);
+        drop(chunks);
+
+        self.entries.borrow_mut().push(Entry::Packed {
+            chunk,
+            start,
+            len: s.len(),
+        });
+        ArenaStr(Repr::Packed(self.packed(chunk, start, s.len())))
+    }
+
+    /// View the `i`th pushed string, or `None` if fewer strings were pushed.
+    pub fn get(&self, i: usize) -> Option<ArenaStr<'_>> {
+        let entries = self.entries.borrow();
+        let repr = match *entries.get(i)? {
+            Entry::Embedded(ref s) => Repr::Embedded(s.clone()),
+            Entry::Packed { chunk, start, len } => Repr::Packed(self.packed(chunk, start, len)),
+        };
+        Some(ArenaStr(repr))
+    }
+
+    /// Number of pushed strings.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn packed(&self, chunk: usize, start: usize, len: usize) -> &str {
+        let chunks = self.chunks.borrow();
+        let s: *const str = &chunks[chunk][start..start + len];
+        // SAFETY: the chunk is never reallocated because it is only appended to within its
+        // capacity, written bytes are never modified, and the chunk is only freed when the
+        // arena is dropped, so the data outlives the `RefCell` borrow for as long as `self`.
+        unsafe { &*s }
+    }
+}
+
+/// A string stored in an [`EmbeddingStrArena`]
+///
+/// Short content is embedded in the view itself; long content borrows the arena's chunk.
+#[derive(Clone)]
+pub struct ArenaStr<'a>(Repr<'a>);
+
+#[derive(Clone)]
+enum Repr<'a> {
+    Embedded(EmbeddingStr),
+    Packed(&'a str),
+}
+
+impl<'a> ArenaStr<'a> {
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        match self.0 {
+            Repr::Embedded(ref s) => s.as_str(),
+            Repr::Packed(s) => s,
+        }
+    }
+
+    /// Whether the content is embedded in the view rather than stored in the arena.
+    pub fn is_embedded(&self) -> bool {
+        matches!(self.0, Repr::Embedded(_))
+    }
+
+    /// The content borrowed for the whole lifetime of the arena, if it is stored there.
+    pub fn as_arena_str(&self) -> Option<&'a str> {
+        match self.0 {
+            Repr::Embedded(_) => None,
+            Repr::Packed(s) => Some(s),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.as_str().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.as_str().is_empty()
+    }
+
+    /// Copy into an `EmbeddingStr` that doesn't borrow the arena.
+    pub fn to_embedding_str(&self) -> EmbeddingStr {
+        match self.0 {
+            Repr::Embedded(ref s) => s.clone(),
+            Repr::Packed(s) => EmbeddingStr::from(s),
+        }
+    }
+}
+
+impl AsRef<str> for ArenaStr<'_> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for ArenaStr<'_> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ArenaStr<'_> {}
+
+impl PartialEq<str> for ArenaStr<'_> {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ArenaStr<'_> {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for ArenaStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for ArenaStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = if self.is_embedded() {
+            "Embedded"
+        } else {
+            "Arena"
+        };
+        f.debug_tuple(name).field(&self.as_str()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: [&str; 6] = [
+        "a",
+        "internationalization",
+        "word",
+        "",
+        "한글 and a few more bytes",
+        "pneumonoultramicroscopicsilicovolcanoconiosis",
+    ];
+
+    #[test]
+    fn test_push_get() {
+        let arena = EmbeddingStrArena::new();
+        assert!(arena.is_empty());
+        let views: Vec<ArenaStr<'_>> = WORDS.iter().map(|w| arena.push(w)).collect();
+        assert_eq!(arena.len(), WORDS.len());
+        for (i, &word) in WORDS.iter().enumerate() {
+            let view = arena.get(i).unwrap();
+            assert_eq!(view, word);
+            assert_eq!(views[i], view);
+            assert_eq!(view.is_embedded(), EmbeddingStr::will_embed_len(word.len()));
+            assert_eq!(view.to_embedding_str(), word);
+        }
+        assert!(arena.get(WORDS.len()).is_none());
+        assert_eq!(arena.chunks.borrow().len(), 1);
+
+        let long = views[1].as_arena_str().unwrap();
+        let next = views[4].as_arena_str().unwrap();
+        assert_eq!(long.as_ptr().wrapping_add(long.len()), next.as_ptr());
+        assert_eq!(views[0].as_arena_str(), None);
+        assert_eq!(
+            format!("{:?} {:?}", views[0], views[1]),
+            r#"Embedded("a") Arena("internationalization")"#
+        );
+    }
+
+    #[test]
+    fn test_views_survive_new_chunks() {
+        let arena = EmbeddingStrArena::new();
+        let huge = "x".repeat(CHUNK_SIZE * 2);
+        let first = arena.push("the first long word");
+        let words: Vec<String> = (0..500).map(|i| format!("word number {:05}", i)).collect();
+        for word in &words {
+            arena.push(word);
+        }
+        let big = arena.push(&huge);
+        arena.push("one after the huge one");
+        assert!(arena.chunks.borrow().len() > 2);
+
+        assert_eq!(first, "the first long word");
+        assert_eq!(big.len(), huge.len());
+        for (i, word) in words.iter().enumerate() {
+            assert_eq!(arena.get(i + 1).unwrap(), word.as_str());
+        }
+        assert_eq!(
+            arena.get(words.len() + 2).unwrap(),
+            "one after the huge one"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Byte layout of `EmbeddingStr`
+//!
+//! The encoding is parameterized by byte order so both layouts can be tested on any target;
+//! outside of tests it is always used with [`Endian::NATIVE`].
+
+// Little Endian 32 bit:
+// heap : |x|l|l|l|p|p|p|p|
+// embed: |x|s|s|s|s|s|s|s|
+// Big Endian 32 bit:
+// heap : |p|p|p|p|l|l|l|x|
+// embed: |s|s|s|s|s|s|s|x|
+//
+// x: discriminant byte; the first bit is 1 if embedded and 0 if heap, the rest of the
+// byte is the len<<1
+// l: the rest of the len<<1 if heap
+// p: ptr to data if heap
+// s: str data (1 utf8 byte) if embedded
+//
+// we can shift the len<<1 because the max slice len is actually isize::MAX as usize:
+// https://stackoverflow.com/questions/32324794/maximum-size-of-an-array-in-32-bits
+
+use std::convert::TryInto;
+
+const WORD_SIZE: usize = std::mem::size_of::<usize>();
+pub(crate) const STR_INNER_SIZE: usize = WORD_SIZE * 2;
+pub(crate) const MAX_EMBEDDED_LEN: usize = STR_INNER_SIZE - 1;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub(crate) enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub(crate) const NATIVE: Endian = if cfg!(target_endian = "little") {
+        Endian::Little
+    } else {
+        Endian::Big
+    };
+
+    /// Index of the discriminant byte, the least significant byte of the len word
+    #[inline]
+    pub(crate) const fn discriminant_index(self) -> usize {
+        match self {
+            Endian::Little => 0,
+            Endian::Big => STR_INNER_SIZE - 1,
+        }
+    }
+
+    /// Index of the first str data byte when embedded
+    #[inline]
+    pub(crate) const fn data_offset(self) -> usize {
+        match self {
+            Endian::Little => 1,
+            Endian::Big => 0,
+        }
+    }
+
+    #[inline]
+    fn word_to_bytes(self, word: usize) -> [u8; WORD_SIZE] {
+        match self {
+            Endian::Little => word.to_le_bytes(),
+            Endian::Big => word.to_be_bytes(),
+        }
+    }
+
+    #[inline]
+    fn word_from_bytes(self, bytes: [u8; WORD_SIZE]) -> usize {
+        match self {
+            Endian::Little => usize::from_le_bytes(bytes),
+            Endian::Big => usize::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// Encode up to `MAX_EMBEDDED_LEN` bytes of str data; bytes past the data are zero.
+#[inline]
+pub(crate) fn encode_embedded(s: &[u8], endian: Endian) -> [u8; STR_INNER_SIZE] {
+    debug_assert!(s.len() <= MAX_EMBEDDED_LEN);
+    let mut raw = [0; STR_INNER_SIZE];
+    let offset = endian.data_offset();
+    raw[offset..offset + s.len()].copy_from_slice(s);
+    raw[endian.discriminant_index()] = ((s.len() as u8) << 1) | 1;
+    raw
+}
+
+/// Same as [`encode_embedded`] for const contexts, copying byte by byte; too slow for the
+/// runtime path.
+pub(crate) const fn encode_embedded_const(s: &[u8], endian: Endian) -> [u8; STR_INNER_SIZE] {
+    debug_assert!(s.len() <= MAX_EMBEDDED_LEN);
+    let mut raw = [0; STR_INNER_SIZE];
+    let offset = endian.data_offset();
+    let mut i = 0;
+    while i < s.len() {
+        raw[offset + i] = s[i];
+        i += 1;
+    }
+    raw[endian.discriminant_index()] = ((s.len() as u8) << 1) | 1;
+    raw
+}
+
+/// Encode the address and length of a heap allocated str.
+#[inline]
+pub(crate) fn encode_heap(addr: usize, len: usize, endian: Endian) -> [u8; STR_INNER_SIZE] {
+    debug_assert!(len <= isize::MAX as usize);
+    let words = match endian {
+        Endian::Little => [len << 1, addr],
+        Endian::Big => [addr, len << 1],
+    };
+    let mut raw = [0; STR_INNER_SIZE];
+    raw[..WORD_SIZE].copy_from_slice(&endian.word_to_bytes(words[0]));
+    raw[WORD_SIZE..].copy_from_slice(&endian.word_to_bytes(words[1]));
+    raw
+}
+
+/// The embedded length, or `None` if `raw` is a heap encoding.
+#[inline]
+pub(crate) fn decode_embedded_len(raw: &[u8; STR_INNER_SIZE], endian: Endian) -> Option<usize> {
+    let discriminant_byte = raw[endian.discriminant_index()];
+    if discriminant_byte & 1 == 0 {
+        None
+    } else {
+        let len = usize::from(discriminant_byte >> 1);
+        debug_assert!(
+            len <= MAX_EMBEDDED_LEN,
+            "corrupted EmbeddingStr: embedded length {} exceeds {}",
+            len,
+            MAX_EMBEDDED_LEN
+        );
+        Some(len)
+    }
+}
+
+/// The embedded str data; `raw` must be an embedded encoding of `len` bytes.
+#[inline]
+pub(crate) fn decode_embedded(raw: &[u8; STR_INNER_SIZE], len: usize, endian: Endian) -> &[u8] {
+    let offset = endian.data_offset();
+    &raw[offset..offset + len]
+}
+
+/// The address and length of the str; `raw` must be a heap encoding.
+#[inline]
+pub(crate) fn decode_heap(raw: &[u8; STR_INNER_SIZE], endian: Endian) -> (usize, usize) {
+    let first = endian.word_from_bytes(raw[..WORD_SIZE].try_into().unwrap());
+    let second = endian.word_from_bytes(raw[WORD_SIZE..].try_into().unwrap());
+    let [len, addr] = match endian {
+        Endian::Little => [first, second],
+        Endian::Big => [second, first],
+    };
+    (addr, len >> 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOTH: [Endian; 2] = [Endian::Little, Endian::Big];
+
+    #[test]
+    fn test_native() {
+        assert_eq!(
+            Endian::NATIVE.word_to_bytes(0x1234),
+            0x1234usize.to_ne_bytes()
+        );
+    }
+
+    #[test]
+    fn test_embedded_layout() {
+        let mut expected = [0; STR_INNER_SIZE];
+        expected[0] = (3 << 1) | 1;
+        expected[1..4].copy_from_slice(b"abc");
+        assert_eq!(encode_embedded(b"abc", Endian::Little), expected);
+        assert_eq!(encode_embedded_const(b"abc", Endian::Little), expected);
+
+        let mut expected = [0; STR_INNER_SIZE];
+        expected[..3].copy_from_slice(b"abc");
+        expected[STR_INNER_SIZE - 1] = (3 << 1) | 1;
+        assert_eq!(encode_embedded(b"abc", Endian::Big), expected);
+        assert_eq!(encode_embedded_const(b"abc", Endian::Big), expected);
+    }
+
+    #[test]
+    fn test_embedded_round_trip() {
+        let full = "x".repeat(MAX_EMBEDDED_LEN);
+        for &endian in BOTH.iter() {
+            for &s in ["", "a", "한글", full.as_str()].iter() {
+                let raw = encode_embedded(s.as_bytes(), endian);
+                assert_eq!(raw, encode_embedded_const(s.as_bytes(), endian));
+                let len = decode_embedded_len(&raw, endian);
+                assert_eq!(len, Some(s.len()), "{:?} {:?}", endian, s);
+                assert_eq!(decode_embedded(&raw, s.len(), endian), s.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_heap_layout() {
+        let addr: usize = 0x0102_0304;
+        let len: usize = 0x50;
+        let mut expected = [0; STR_INNER_SIZE];
+        expected[..WORD_SIZE].copy_from_slice(&(len << 1).to_le_bytes());
+        expected[WORD_SIZE..].copy_from_slice(&addr.to_le_bytes());
+        assert_eq!(encode_heap(addr, len, Endian::Little), expected);
+
+        let mut expected = [0; STR_INNER_SIZE];
+        expected[..WORD_SIZE].copy_from_slice(&addr.to_be_bytes());
+        expected[WORD_SIZE..].copy_from_slice(&(len << 1).to_be_bytes());
+        assert_eq!(encode_heap(addr, len, Endian::Big), expected);
+        assert_eq!(expected[STR_INNER_SIZE - 1], 0xa0);
+    }
+
+    #[test]
+    fn test_heap_round_trip() {
+        let s: Box<str> = "something longer than 15 bytes".into();
+        let addr = s.as_ptr() as usize;
+        for &endian in BOTH.iter() {
+            let raw = encode_heap(addr, s.len(), endian);
+            assert_eq!(decode_embedded_len(&raw, endian), None, "{:?}", endian);
+            assert_eq!(decode_heap(&raw, endian), (addr, s.len()), "{:?}", endian);
+            let (addr, len) = decode_heap(&raw, endian);
+            // SAFETY: `addr` and `len` were decoded from the live box `s`
+            let decoded = unsafe {
+                std::str::from_utf8_unchecked(std::slice::from_raw_parts(addr as *const u8, len))
+            };
+            assert_eq!(decoded, &*s);
+        }
+        for &endian in BOTH.iter() {
+            let raw = encode_heap(1, isize::MAX as usize, endian);
+            assert_eq!(decode_heap(&raw, endian), (1, isize::MAX as usize));
+        }
+    }
+}
@@ -1,35 +1,33 @@
 //! Short string embedding for std `str`
 
-use std::borrow::Cow;
-use std::mem::{self, MaybeUninit};
+use std::borrow::{Borrow, Cow};
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fmt;
+use std::mem;
+use std::ops::{Bound, Index, Range, RangeBounds};
 use std::ptr;
 
+mod arena;
+mod layout;
+#[cfg(feature = "pool")]
+mod pool;
+mod string;
+
+pub use arena::{ArenaStr, EmbeddingStrArena};
+use layout::{Endian, MAX_EMBEDDED_LEN, STR_INNER_SIZE};
+#[cfg(feature = "pool")]
+pub use pool::StrPool;
+pub use string::EmbeddingString;
+
 /// Replacement of Box<[std::str::str]> for short string embedding
 ///
 /// When string size is smaller than `std::mem::size_of::<usize>*2-1`,
 /// embed the string content into itself rather than holding the pointer.
 #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
 #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
-pub struct EmbeddingStr(MaybeUninit<[u8; STR_INNER_SIZE]>);
-
-// Little Endian 32 bit:
-// heap : |x|l|l|l|p|p|p|p|
-// embed: |x|s|s|s|s|s|s|s|
-// Big Endian 32 bit:
-// heap : |p|p|p|p|l|l|l|x|
-// embed: |s|s|s|s|s|s|s|x|
-//
-// x: discriminant byte; the first bit is 1 if embedded and 0 if heap, the rest of the
-// byte is the len<<1
-// l: the rest of the len<<1 if heap
-// p: ptr to data if heap
-// s: str data (1 utf8 byte) if embedded
-//
-// we can shift the len<<1 because the max slice len is actually isize::MAX as usize:
-// https://stackoverflow.com/questions/32324794/maximum-size-of-an-array-in-32-bits
-
-const STR_INNER_SIZE: usize = std::mem::size_of::<usize>() * 2;
-const MAX_EMBEDDED_LEN: usize = STR_INNER_SIZE - 1;
+pub struct EmbeddingStr([u8; STR_INNER_SIZE]);
 
 #[derive(Eq, PartialEq, Debug)]
 pub enum EmbeddingStrMode {
@@ -37,60 +35,231 @@ pub enum EmbeddingStrMode {
     Embedded,
 }
 
-#[inline]
-fn len_least_significant([len, ptr]: [usize; 2]) -> [usize; 2] {
-    if cfg!(target_endian = "little") {
-        [len, ptr]
-    } else {
-        [ptr, len]
-    }
+/// Destructured content of an [`EmbeddingStr`], see [`EmbeddingStr::into_parts`]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum EmbeddingStrParts {
+    /// Inline buffer and the length of the str data in it
+    Embedded([u8; MAX_EMBEDDED_LEN], usize),
+    Boxed(Box<str>),
 }
 
 impl EmbeddingStr {
     fn new_embedded(s: &str) -> Self {
-        debug_assert!(s.len() <= MAX_EMBEDDED_LEN);
-        let mut new = std::mem::MaybeUninit::uninit();
-        let mut_ptr = new.as_mut_ptr() as *mut u8;
-        let encoded_len = ((s.len() as u8) << 1) | 1;
-        unsafe {
-            if cfg!(target_endian = "little") {
-                std::ptr::copy_nonoverlapping(s.as_ptr(), mut_ptr.add(1), s.len());
-                mut_ptr.write(encoded_len);
-            } else {
-                std::ptr::copy_nonoverlapping(s.as_ptr(), mut_ptr, s.len());
-                mut_ptr.add(STR_INNER_SIZE - 1).write(encoded_len);
-            }
-        }
-        Self(new)
+        Self(layout::encode_embedded(s.as_bytes(), Endian::NATIVE))
     }
 
+    // The heap encoding owns the allocation of a `Box<str>` released with `Box::into_raw`;
+    // `heap_ptr` gives back that exact pointer and `Drop` reclaims it.
     fn new_heap(s: Box<str>) -> Self {
         let len = s.len();
-        let ptr = Box::into_raw(s) as *mut u8 as usize;
-        let inner = len_least_significant([len << 1, ptr]);
-        Self(unsafe { mem::transmute(inner) })
+        let addr = Box::into_raw(s) as *mut u8 as usize;
+        Self(layout::encode_heap(addr, len, Endian::NATIVE))
+    }
+
+    #[cfg(test)]
+    fn from_raw_bytes(bytes: [u8; STR_INNER_SIZE]) -> Self {
+        Self(bytes)
     }
 
-    // SAFETY: must be in fully initialized heap mode to call
+    // SAFETY: must be in heap mode to call, otherwise the decoded address is str data
     unsafe fn heap_ptr(&self) -> *const str {
-        let inner = mem::transmute_copy(&self.0);
-        let [len, ptr] = len_least_significant(inner);
-        ptr::slice_from_raw_parts(ptr as *const u8, len >> 1) as *const str
+        let (addr, len) = layout::decode_heap(&self.0, Endian::NATIVE);
+        ptr::slice_from_raw_parts(addr as *const u8, len) as *const str
     }
 
-    fn embedded_len(&self) -> Option<usize> {
-        // SAFETY: the least significant byte of the structure is always initialized
-        let discriminant_byte = unsafe {
-            if cfg!(target_endian = "little") {
-                std::mem::transmute_copy::<_, u8>(&self.0)
-            } else {
-                self.0.as_ptr().cast::<u8>().add(STR_INNER_SIZE - 1).read()
-            }
-        };
-        if discriminant_byte & 1 == 0 {
-            None
+    /// Embed a string literal at compile time.
+    ///
+    /// Unlike `From<&str>`, which boxes long strings at runtime, this only ever embeds. Used in
+    /// a `const` or `static` initializer, an over-long literal is a compile error, so the
+    /// constant is guaranteed to never allocate:
+    ///
+    /// ```
+    /// use embed_str::{EmbeddingStr, EmbeddingStrMode};
+    ///
+    /// const GREETING: EmbeddingStr = EmbeddingStr::from_static_embedded("hello");
+    /// assert_eq!(GREETING.mode(), EmbeddingStrMode::Embedded);
+    /// assert_eq!(GREETING.as_str(), "hello");
+    /// ```
+    ///
+    /// ```compile_fail
+    /// use embed_str::EmbeddingStr;
+    ///
+    /// const TOO_LONG: EmbeddingStr = EmbeddingStr::from_static_embedded("a 20 byte literal...");
+    /// let _ = TOO_LONG;
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `s` is too long to be embedded, when called at runtime.
+    pub const fn from_static_embedded(s: &'static str) -> EmbeddingStr {
+        assert!(
+            Self::will_embed_len(s.len()),
+            "string literal is too long to be embedded"
+        );
+        Self(layout::encode_embedded_const(s.as_bytes(), Endian::NATIVE))
+    }
+
+    /// Whether a string of `len` bytes is embedded rather than boxed.
+    #[inline]
+    pub const fn will_embed_len(len: usize) -> bool {
+        len <= MAX_EMBEDDED_LEN
+    }
+
+    /// Length of the embedded content, or `None` if the content is boxed.
+    ///
+    /// This only reads the discriminant byte, so it's a cheap way to branch on the mode.
+    #[inline]
+    pub fn embedded_len(&self) -> Option<usize> {
+        layout::decode_embedded_len(&self.0, Endian::NATIVE)
+    }
+
+    // str data of an embedded value, including the zero padding after it
+    #[inline]
+    fn embedded_buf(&self) -> &[u8] {
+        layout::decode_embedded(&self.0, MAX_EMBEDDED_LEN, Endian::NATIVE)
+    }
+
+    /// Format `args` into the embedded representation without touching the heap.
+    ///
+    /// Returns `None` if the formatted output doesn't fit in the embedded buffer.
+    pub fn try_embed_fmt(args: fmt::Arguments<'_>) -> Option<Self> {
+        let mut buf = EmbeddedBuf::new();
+        fmt::write(&mut buf, args).ok()?;
+        Some(Self::new_embedded(buf.as_str()))
+    }
+
+    /// Format `args` into a new string.
+    ///
+    /// Output is written into an inline buffer first and only spills to the heap once it
+    /// outgrows the embedded size, so short results never allocate.
+    pub fn from_fmt(args: fmt::Arguments<'_>) -> Self {
+        let mut buf = SpillBuf::new();
+        fmt::write(&mut buf, args)
+            .expect("a Display implementation returned an error unexpectedly");
+        buf.into_embedding_str()
+    }
+
+    /// Embed `s` if it is short enough, otherwise build the value with `f`.
+    ///
+    /// Useful to route long strings through an interner or another custom allocation.
+    #[inline]
+    pub fn with_embedded_or_else(s: &str, f: impl FnOnce(&str) -> EmbeddingStr) -> Self {
+        if Self::will_embed_len(s.len()) {
+            Self::new_embedded(s)
+        } else {
+            f(s)
+        }
+    }
+
+    /// Convert each string, embedding short ones and reusing the buffers of long ones.
+    ///
+    /// A long string is only reallocated if it has spare capacity to shed.
+    pub fn from_strings(v: Vec<String>) -> Vec<EmbeddingStr> {
+        v.into_iter().map(EmbeddingStr::from).collect()
+    }
+
+    /// Convert a vector of bytes to an `EmbeddingStr` without checking that it is UTF-8.
+    ///
+    /// Short content is copied inline and the vector is freed; long content reuses the
+    /// vector's buffer as the boxed allocation (shrinking it to fit if it has spare capacity).
+    ///
+    /// # Safety
+    ///
+    /// `v` must contain valid UTF-8, with the same contract as [`String::from_utf8_unchecked`].
+    pub unsafe fn from_utf8_vec_unchecked(v: Vec<u8>) -> Self {
+        // SAFETY: the caller guarantees `v` is valid UTF-8
+        if Self::will_embed_len(v.len()) {
+            Self::new_embedded(std::str::from_utf8_unchecked(&v))
         } else {
-            Some(usize::from(discriminant_byte >> 1))
+            Self::new_heap(String::from_utf8_unchecked(v).into_boxed_str())
+        }
+    }
+
+    /// Destructure into the embedded bytes or the boxed allocation.
+    pub fn into_parts(self) -> EmbeddingStrParts {
+        match self.into_heap() {
+            Ok(boxed) => EmbeddingStrParts::Boxed(boxed),
+            Err(this) => {
+                let len = this.len();
+                let mut buf = [0; MAX_EMBEDDED_LEN];
+                buf[..len].copy_from_slice(this.as_bytes());
+                EmbeddingStrParts::Embedded(buf, len)
+            }
+        }
+    }
+
+    // hand over the boxed allocation, or give back `self` if embedded
+    fn into_heap(self) -> Result<Box<str>, Self> {
+        if self.embedded_len().is_some() {
+            return Err(self);
+        }
+        let this = mem::ManuallyDrop::new(self);
+        // SAFETY: the allocation is handed over to the box and `this` is never dropped
+        Ok(unsafe { Box::from_raw(this.heap_ptr() as *mut str) })
+    }
+
+    /// Convert into a `Cow<'static, str>`, which is always `Cow::Owned`.
+    ///
+    /// The two modes are asymmetric: a boxed value hands its allocation over to the `String`
+    /// without copying, while embedded data lives inside `self` and has to be copied into a
+    /// new allocation.
+    pub fn into_cow_static(self) -> Cow<'static, str> {
+        Cow::Owned(String::from(self))
+    }
+
+    /// Append the binary encoding of the string to `out`: the byte length as an unsigned
+    /// LEB128 varint, followed by the UTF-8 bytes.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let bytes = self.as_bytes();
+        let mut len = bytes.len();
+        while len >= 0x80 {
+            out.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        out.push(len as u8);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Decode a string written by [`encode`](Self::encode) from the start of `input`.
+    ///
+    /// Returns the string, embedded if short enough, and the rest of the input.
+    pub fn decode(input: &[u8]) -> Result<(EmbeddingStr, &[u8]), DecodeError> {
+        let mut len: usize = 0;
+        let mut shift = 0;
+        let mut rest = input;
+        loop {
+            let (&byte, tail) = rest.split_first().ok_or(DecodeError::Truncated)?;
+            rest = tail;
+            let value = usize::from(byte & 0x7f);
+            if shift >= usize::BITS || (value << shift) >> shift != value {
+                return Err(DecodeError::LengthOverflow);
+            }
+            len |= value << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        if rest.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (bytes, rest) = rest.split_at(len);
+        let s = std::str::from_utf8(bytes).map_err(DecodeError::InvalidUtf8)?;
+        Ok((EmbeddingStr::from(s), rest))
+    }
+
+    /// Reconstruct from the parts returned by [`into_parts`](Self::into_parts).
+    ///
+    /// Fails if the embedded length exceeds the buffer size or the embedded bytes are not
+    /// valid UTF-8. A short boxed str is re-embedded.
+    pub fn from_parts(parts: EmbeddingStrParts) -> Result<Self, FromPartsError> {
+        match parts {
+            EmbeddingStrParts::Embedded(buf, len) => {
+                let bytes = buf.get(..len).ok_or(FromPartsError::TooLong(len))?;
+                let s = std::str::from_utf8(bytes).map_err(FromPartsError::InvalidUtf8)?;
+                Ok(Self::new_embedded(s))
+            }
+            EmbeddingStrParts::Boxed(s) => Ok(Self::from(s)),
         }
     }
 
@@ -102,27 +271,535 @@ impl EmbeddingStr {
         }
     }
 
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self.embedded_len() {
+            Some(len) => len,
+            None => self.as_str().len(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn as_str(&self) -> &str {
         match self.embedded_len() {
+            // SAFETY: in heap mode, the pointer is a live `Box<str>` owned by `self`
             None => unsafe { &*self.heap_ptr() },
             Some(len) => {
-                let ptr = self.0.as_ptr().cast::<u8>();
-                let start = if cfg!(target_endian = "little") {
-                    unsafe { ptr.add(1) }
-                } else {
-                    ptr
-                };
-                let sptr = ptr::slice_from_raw_parts(start, len) as *const str;
-                unsafe { &*sptr }
+                let data = layout::decode_embedded(&self.0, len, Endian::NATIVE);
+                // SAFETY: embedded data is always copied from a whole str
+                unsafe { std::str::from_utf8_unchecked(data) }
+            }
+        }
+    }
+
+    /// Same as [`as_str`](Self::as_str), spelled out for code holding a `&mut EmbeddingStr`
+    /// that only needs a shared view.
+    #[inline]
+    pub fn as_str_ref(&self) -> &str {
+        self.as_str()
+    }
+
+    /// The raw bytes of an embedded value including the discriminant byte, or `None` if boxed.
+    ///
+    /// Bytes past the embedded length are zero.
+    pub fn embedded_raw(&self) -> Option<&[u8; STR_INNER_SIZE]> {
+        self.embedded_len()?;
+        Some(&self.0)
+    }
+
+    /// Length in bytes of the longest common prefix of `self` and `other`.
+    ///
+    /// Bytes are compared as-is, so the prefix may end inside a multibyte char.
+    pub fn common_prefix_len(&self, other: &EmbeddingStr) -> usize {
+        match (self.embedded_len(), other.embedded_len()) {
+            (Some(len), Some(other_len)) => {
+                // embedded data is zero padded, so comparing the whole fixed-size buffers
+                // then clamping to the shorter length gives the same result
+                let differ = self
+                    .embedded_buf()
+                    .iter()
+                    .zip(other.embedded_buf())
+                    .position(|(a, b)| a != b)
+                    .unwrap_or(MAX_EMBEDDED_LEN);
+                differ.min(len).min(other_len)
+            }
+            _ => self
+                .as_bytes()
+                .iter()
+                .zip(other.as_bytes())
+                .take_while(|(a, b)| a == b)
+                .count(),
+        }
+    }
+
+    /// Number of occurrences of `c`.
+    ///
+    /// With the `memchr` feature, ASCII chars are counted with `memchr`.
+    pub fn count_char(&self, c: char) -> usize {
+        #[cfg(feature = "memchr")]
+        {
+            if c.is_ascii() {
+                return memchr::memchr_iter(c as u8, self.as_bytes()).count();
+            }
+        }
+        self.as_str().matches(c).count()
+    }
+
+    /// Byte index of the first occurrence of `c`.
+    ///
+    /// With the `memchr` feature, ASCII chars are searched with `memchr`.
+    pub fn find(&self, c: char) -> Option<usize> {
+        #[cfg(feature = "memchr")]
+        {
+            if c.is_ascii() {
+                return memchr::memchr(c as u8, self.as_bytes());
+            }
+        }
+        self.as_str().find(c)
+    }
+
+    /// Byte offset of the first char that is one of `delims`.
+    pub fn find_any(&self, delims: &[char]) -> Option<usize> {
+        self.as_str().find(delims)
+    }
+
+    /// Whether the UTF-8 bytes of the string start with `prefix`.
+    #[inline]
+    pub fn starts_with_bytes(&self, prefix: &[u8]) -> bool {
+        match self.embedded_len() {
+            Some(len) => prefix.len() <= len && self.embedded_buf().starts_with(prefix),
+            None => self.as_bytes().starts_with(prefix),
+        }
+    }
+
+    /// Whether the string ends with `suffix`, ignoring ASCII case.
+    pub fn ends_with_ignore_ascii_case(&self, suffix: &str) -> bool {
+        let bytes = self.as_bytes();
+        bytes.len() >= suffix.len()
+            && bytes[bytes.len() - suffix.len()..].eq_ignore_ascii_case(suffix.as_bytes())
+    }
+
+    /// The `n`th char (not byte) of the string, or `None` if there are fewer chars.
+    #[inline]
+    pub fn nth_char(&self, n: usize) -> Option<char> {
+        self.as_str().chars().nth(n)
+    }
+
+    /// The char starting at byte index `i`, or `None` if `i` is out of bounds or not on a
+    /// char boundary.
+    #[inline]
+    pub fn char_at_byte(&self, i: usize) -> Option<char> {
+        self.as_str().get(i..)?.chars().next()
+    }
+
+    /// The byte at index `i`, or `None` if out of bounds.
+    #[inline]
+    pub fn byte_at(&self, i: usize) -> Option<u8> {
+        match self.embedded_len() {
+            Some(len) => (i < len).then(|| self.embedded_buf()[i]),
+            None => self.as_bytes().get(i).copied(),
+        }
+    }
+
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
+
+    /// Copy the string with every `from` replaced by `to`.
+    ///
+    /// When both chars are ASCII the length can't change, so the bytes are replaced in a
+    /// straight copy and the result keeps the mode of `self`.
+    pub fn replace_char(&self, from: char, to: char) -> EmbeddingStr {
+        if !(from.is_ascii() && to.is_ascii()) {
+            let mut buf = [0; 4];
+            let to: &str = to.encode_utf8(&mut buf);
+            return EmbeddingStr::from(self.as_str().replace(from, to));
+        }
+        let (from, to) = (from as u8, to as u8);
+        let replace = |bytes: &mut [u8]| {
+            bytes
+                .iter_mut()
+                .filter(|b| **b == from)
+                .for_each(|b| *b = to)
+        };
+        match self.embedded_len() {
+            Some(len) => {
+                let mut raw = self.0;
+                let offset = Endian::NATIVE.data_offset();
+                replace(&mut raw[offset..offset + len]);
+                Self(raw)
+            }
+            None => {
+                let mut bytes = Box::<[u8]>::from(self.as_bytes());
+                replace(&mut bytes);
+                // SAFETY: swapping one ASCII byte for another keeps the bytes valid UTF-8
+                Self::new_heap(unsafe { std::str::from_boxed_utf8_unchecked(bytes) })
+            }
+        }
+    }
+
+    /// Copy the string with all leading and trailing `pat` removed, embedding if short.
+    pub fn trim_matches_owned(&self, pat: char) -> EmbeddingStr {
+        EmbeddingStr::from(self.as_str().trim_matches(pat))
+    }
+
+    /// Copy the byte `range` into a new string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or doesn't lie on char boundaries.
+    #[track_caller]
+    pub fn substr(&self, range: impl RangeBounds<usize>) -> EmbeddingStr {
+        EmbeddingStr::from(&self[range])
+    }
+
+    /// Split into two strings at the byte index `mid`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid` is out of bounds or not on a char boundary.
+    #[track_caller]
+    pub fn split_at(&self, mid: usize) -> (EmbeddingStr, EmbeddingStr) {
+        let mid = check_range(self.as_str(), mid..).start;
+        let (left, right) = self.as_str().split_at(mid);
+        (EmbeddingStr::from(left), EmbeddingStr::from(right))
+    }
+
+    /// Split on the last occurrence of `delim`, copying both sides into new strings.
+    ///
+    /// Returns `None` if `delim` doesn't occur.
+    pub fn rsplit_once_owned(&self, delim: char) -> Option<(EmbeddingStr, EmbeddingStr)> {
+        let (left, right) = self.as_str().rsplit_once(delim)?;
+        Some((EmbeddingStr::from(left), EmbeddingStr::from(right)))
+    }
+
+    /// Iterate over the lines of the string as owned values, embedding short lines.
+    ///
+    /// Lines are split the same way as [`str::lines`].
+    pub fn lines_owned(&self) -> impl Iterator<Item = EmbeddingStr> + '_ {
+        self.as_str().lines().map(EmbeddingStr::from)
+    }
+
+    /// Number of lines, counted the same way as `str::lines().count()`.
+    pub fn count_lines(&self) -> usize {
+        match self.embedded_len() {
+            Some(_) => {
+                let bytes = self.as_bytes();
+                match bytes.last() {
+                    None => 0,
+                    Some(&last) => {
+                        let newlines = bytes.iter().filter(|&&b| b == b'\n').count();
+                        newlines + usize::from(last != b'\n')
+                    }
+                }
+            }
+            None => self.as_str().lines().count(),
+        }
+    }
+
+    /// Whether every char is whitespace, as defined by [`char::is_whitespace`].
+    ///
+    /// Returns `true` for the empty string.
+    pub fn is_whitespace_only(&self) -> bool {
+        if self.embedded_len().is_some() {
+            let bytes = self.as_bytes();
+            // `u8::is_ascii_whitespace` excludes U+000B, which `char::is_whitespace` accepts
+            if bytes.is_ascii() {
+                return bytes
+                    .iter()
+                    .all(|b| matches!(b, b'\t' | b'\n' | b'\x0B' | b'\x0C' | b'\r' | b' '));
+            }
+        }
+        self.as_str().chars().all(char::is_whitespace)
+    }
+
+    /// Left-justify the string in a field of `width` chars, padding with `fill`.
+    ///
+    /// Same as Python's `str.ljust`: the content is returned unchanged if it is not
+    /// shorter than `width`.
+    #[track_caller]
+    pub fn ljust(&self, width: usize, fill: char) -> EmbeddingStr {
+        self.justify(width, fill, true)
+    }
+
+    /// Right-justify the string in a field of `width` chars, padding with `fill`.
+    ///
+    /// Same as Python's `str.rjust`: the content is returned unchanged if it is not
+    /// shorter than `width`.
+    #[track_caller]
+    pub fn rjust(&self, width: usize, fill: char) -> EmbeddingStr {
+        self.justify(width, fill, false)
+    }
+
+    #[track_caller]
+    fn justify(&self, width: usize, fill: char, left: bool) -> EmbeddingStr {
+        let s = self.as_str();
+        let char_len = s.chars().count();
+        if char_len >= width {
+            return self.clone();
+        }
+        let padding = width - char_len;
+        let mut fill_buf = [0; 4];
+        let fill = &*fill.encode_utf8(&mut fill_buf);
+        let len = fill
+            .len()
+            .checked_mul(padding)
+            .and_then(|len| len.checked_add(s.len()));
+        let pad = std::iter::repeat_n(fill, padding);
+        if left {
+            Self::from_pieces(len, std::iter::once(s).chain(pad))
+        } else {
+            Self::from_pieces(len, pad.chain(std::iter::once(s)))
+        }
+    }
+
+    /// Concatenate `parts` into a single string.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length overflows `usize`.
+    #[track_caller]
+    pub fn concat(parts: &[EmbeddingStr]) -> EmbeddingStr {
+        Self::join(parts, "")
+    }
+
+    /// Concatenate `parts` into a single string, placing `sep` between each of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length overflows `usize`.
+    #[track_caller]
+    pub fn join(parts: &[EmbeddingStr], sep: &str) -> EmbeddingStr {
+        let len = sep
+            .len()
+            .checked_mul(parts.len().saturating_sub(1))
+            .and_then(|len| {
+                parts
+                    .iter()
+                    .try_fold(len, |len, p| len.checked_add(p.len()))
+            });
+        let pieces = parts.iter().enumerate().flat_map(|(i, p)| {
+            let sep = if i == 0 { "" } else { sep };
+            std::iter::once(sep).chain(std::iter::once(p.as_str()))
+        });
+        Self::from_pieces(len, pieces)
+    }
+
+    /// Create a new string by repeating this one `n` times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total length overflows `usize`.
+    #[track_caller]
+    pub fn repeat(&self, n: usize) -> EmbeddingStr {
+        let len = self.len().checked_mul(n);
+        Self::from_pieces(len, std::iter::repeat_n(self.as_str(), n))
+    }
+
+    /// Concatenate the values of `iter` into a single string, placing `sep` between each.
+    ///
+    /// The result is built inline and only moves to the heap once it outgrows the embedded
+    /// size.
+    pub fn join_iter<I: IntoIterator<Item = EmbeddingStr>>(iter: I, sep: &str) -> EmbeddingStr {
+        let mut buf = SpillBuf::new();
+        for (i, s) in iter.into_iter().enumerate() {
+            if i != 0 {
+                buf.push_str(sep);
+            }
+            buf.push_str(s.as_str());
+        }
+        buf.into_embedding_str()
+    }
+
+    // `len` is the checked total length of `pieces`; `None` means it overflowed
+    #[track_caller]
+    fn from_pieces<'a>(len: Option<usize>, pieces: impl Iterator<Item = &'a str>) -> Self {
+        let len = len.expect("EmbeddingStr length overflows usize");
+        if len == 0 {
+            // `pieces` can be arbitrarily many empty strs, e.g. `"".repeat(usize::MAX)`
+            return Self::default();
+        }
+        if Self::will_embed_len(len) {
+            let mut buf = EmbeddedBuf::new();
+            for piece in pieces {
+                buf.push_str(piece)
+                    .expect("pieces longer than their total length");
+            }
+            Self::new_embedded(buf.as_str())
+        } else {
+            let mut s = String::with_capacity(len);
+            pieces.for_each(|piece| s.push_str(piece));
+            Self::new_heap(s.into_boxed_str())
+        }
+    }
+}
+
+/// Sort `v` and remove duplicated values
+pub fn dedup_sorted(v: &mut Vec<EmbeddingStr>) {
+    v.sort_unstable();
+    v.dedup();
+}
+
+/// Validate a byte range against `s`, panicking with std-like messages
+#[track_caller]
+pub(crate) fn check_range(s: &str, range: impl RangeBounds<usize>) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start
+            .checked_add(1)
+            .expect("attempted to index str after usize::MAX"),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end
+            .checked_add(1)
+            .expect("attempted to index str up to usize::MAX"),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => s.len(),
+    };
+    for &index in [start, end].iter() {
+        if index > s.len() {
+            panic!(
+                "byte index {} is out of bounds of string of length {}",
+                index,
+                s.len()
+            );
+        }
+        if !s.is_char_boundary(index) {
+            panic!("byte index {} is not a char boundary", index);
+        }
+    }
+    if start > end {
+        panic!("begin <= end ({} <= {}) when slicing string", start, end);
+    }
+    start..end
+}
+
+/// `HashMap` keyed by [`EmbeddingStr`] using the Fx hasher, which is fast on short keys
+///
+/// Lookups take `&str` directly through `Borrow<str>`. Fx is not resistant to HashDoS, so
+/// don't use it for keys controlled by untrusted input.
+#[cfg(feature = "rustc-hash")]
+pub type EmbeddingStrMap<V> =
+    std::collections::HashMap<EmbeddingStr, V, std::hash::BuildHasherDefault<rustc_hash::FxHasher>>;
+
+/// `HashSet` of [`EmbeddingStr`] using the Fx hasher, see [`EmbeddingStrMap`]
+#[cfg(feature = "rustc-hash")]
+pub type EmbeddingStrSet =
+    std::collections::HashSet<EmbeddingStr, std::hash::BuildHasherDefault<rustc_hash::FxHasher>>;
+
+/// Values of `set` in the half-open range `low..high`, in sorted order
+///
+/// # Panics
+///
+/// Panics if `low > high`.
+pub fn range_str<'a>(
+    set: &'a BTreeSet<EmbeddingStr>,
+    low: &str,
+    high: &str,
+) -> impl Iterator<Item = &'a EmbeddingStr> {
+    set.range::<str, _>((Bound::Included(low), Bound::Excluded(high)))
+}
+
+/// Fixed-size stack buffer holding at most `MAX_EMBEDDED_LEN` bytes of str data
+pub(crate) struct EmbeddedBuf {
+    buf: [u8; MAX_EMBEDDED_LEN],
+    len: usize,
+}
+
+impl EmbeddedBuf {
+    pub(crate) fn new() -> Self {
+        Self {
+            buf: [0; MAX_EMBEDDED_LEN],
+            len: 0,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        // SAFETY: only whole `&str`s are ever copied into the buffer
+        unsafe { std::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    pub(crate) fn push_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > MAX_EMBEDDED_LEN {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    /// Shorten to `new_len` bytes, which must be a char boundary of the content.
+    pub(crate) fn truncate(&mut self, new_len: usize) {
+        assert!(self.as_str().is_char_boundary(new_len));
+        self.len = new_len;
+    }
+}
+
+impl fmt::Write for EmbeddedBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s)
+    }
+}
+
+/// Inline buffer that spills into a `String` once it outgrows the embedded size
+pub(crate) enum SpillBuf {
+    Embedded(EmbeddedBuf),
+    Heap(String),
+}
+
+impl SpillBuf {
+    pub(crate) fn new() -> Self {
+        SpillBuf::Embedded(EmbeddedBuf::new())
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            SpillBuf::Embedded(buf) => buf.as_str(),
+            SpillBuf::Heap(s) => s,
+        }
+    }
+
+    pub(crate) fn push_str(&mut self, s: &str) {
+        match self {
+            SpillBuf::Embedded(buf) => {
+                if buf.push_str(s).is_err() {
+                    let mut heap = String::with_capacity(buf.len + s.len());
+                    heap.push_str(buf.as_str());
+                    heap.push_str(s);
+                    *self = SpillBuf::Heap(heap);
+                }
             }
+            SpillBuf::Heap(heap) => heap.push_str(s),
+        }
+    }
+
+    pub(crate) fn into_embedding_str(self) -> EmbeddingStr {
+        match self {
+            SpillBuf::Embedded(buf) => EmbeddingStr::new_embedded(buf.as_str()),
+            SpillBuf::Heap(s) => EmbeddingStr::from(s),
         }
     }
 }
 
+impl fmt::Write for SpillBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
 impl Drop for EmbeddingStr {
     fn drop(&mut self) {
         match self.mode() {
             EmbeddingStrMode::Boxed => {
+                // SAFETY: in heap mode, `self` owns the allocation and it is freed only here
                 let _boxed = unsafe { Box::from_raw(self.heap_ptr() as *mut str) };
             }
             EmbeddingStrMode::Embedded => {
@@ -132,71 +809,410 @@ impl Drop for EmbeddingStr {
     }
 }
 
-impl std::fmt::Display for EmbeddingStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Display::fmt(self.as_str(), f)
+impl<R: RangeBounds<usize>> Index<R> for EmbeddingStr {
+    type Output = str;
+
+    #[inline]
+    #[track_caller]
+    fn index(&self, range: R) -> &str {
+        let range = check_range(self.as_str(), range);
+        &self.as_str()[range]
     }
 }
 
-impl std::fmt::Debug for EmbeddingStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}({:?})", self.mode(), self.as_str())
+impl Borrow<str> for EmbeddingStr {
+    #[inline]
+    fn borrow(&self) -> &str {
+        self.as_str()
     }
 }
 
-impl From<String> for EmbeddingStr {
-    #[inline(always)]
-    fn from(s: String) -> Self {
-        Self::from(Cow::Owned(s))
+impl AsRef<str> for EmbeddingStr {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
     }
 }
 
-impl From<&'_ str> for EmbeddingStr {
-    #[inline(always)]
-    fn from(s: &str) -> Self {
-        EmbeddingStr::from(Cow::Borrowed(s))
+impl AsRef<[u8]> for EmbeddingStr {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
     }
 }
 
-impl From<Cow<'_, str>> for EmbeddingStr {
+/// An empty embedded string, so `mem::take` never allocates.
+impl Default for EmbeddingStr {
     #[inline]
-    fn from(s: Cow<'_, str>) -> Self {
-        if s.len() <= MAX_EMBEDDED_LEN {
-            Self::new_embedded(&s)
-        } else {
-            Self::new_heap(s.into_owned().into_boxed_str())
-        }
+    fn default() -> Self {
+        Self::new_embedded("")
     }
 }
 
-impl From<Box<str>> for EmbeddingStr {
+impl Clone for EmbeddingStr {
     #[inline]
-    fn from(s: Box<str>) -> Self {
-        if s.len() <= MAX_EMBEDDED_LEN {
-            Self::new_embedded(&s)
-        } else {
-            Self::new_heap(s)
+    fn clone(&self) -> Self {
+        match self.mode() {
+            // embedded content is plain bytes; copying the whole structure is a full clone
+            EmbeddingStrMode::Embedded => Self(self.0),
+            // never copy the pointer word; the clone must own its own allocation
+            EmbeddingStrMode::Boxed => Self::new_heap(Box::from(self.as_str())),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl PartialEq for EmbeddingStr {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // identical bytes are either the same embedded content or the same heap allocation,
+        // so boxed values sharing a pointer are equal without reading the heap
+        self.0 == other.0 || self.as_str() == other.as_str()
+    }
+}
 
-    #[test]
-    fn test_lifecycle() {
-        assert_eq!(STR_INNER_SIZE, 16);
+impl Eq for EmbeddingStr {}
 
-        let s = EmbeddingStr::from("");
-        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
-        assert_eq!(s.as_str(), "");
-        let s = EmbeddingStr::from("a");
-        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
-        assert_eq!(s.as_str(), "a");
-        let s = EmbeddingStr::from("abcdxyz01");
-        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
-        assert_eq!(s.as_str(), "abcdxyz01");
+impl PartialEq<str> for EmbeddingStr {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&'_ str> for EmbeddingStr {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<&&'_ str> for EmbeddingStr {
+    #[inline]
+    fn eq(&self, other: &&&str) -> bool {
+        self.as_str() == **other
+    }
+}
+
+// `Hash`, `Eq` and `Ord` must agree with `str` for `Borrow<str>`, so they all delegate to it
+impl std::hash::Hash for EmbeddingStr {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialOrd for EmbeddingStr {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for EmbeddingStr {
+    #[inline]
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl std::fmt::Display for EmbeddingStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+/// `{:?}` prints the compact `Embedded("abc")` form; `{:#?}` prints the mode, length and
+/// value as struct fields.
+impl std::fmt::Debug for EmbeddingStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            f.debug_struct("EmbeddingStr")
+                .field("mode", &self.mode())
+                .field("len", &self.len())
+                .field("value", &self.as_str())
+                .finish()
+        } else {
+            write!(f, "{:?}({:?})", self.mode(), self.as_str())
+        }
+    }
+}
+
+impl From<String> for EmbeddingStr {
+    #[inline(always)]
+    fn from(s: String) -> Self {
+        Self::from(Cow::Owned(s))
+    }
+}
+
+impl From<fmt::Arguments<'_>> for EmbeddingStr {
+    #[inline]
+    fn from(args: fmt::Arguments<'_>) -> Self {
+        Self::from_fmt(args)
+    }
+}
+
+impl From<&'_ str> for EmbeddingStr {
+    #[inline(always)]
+    fn from(s: &str) -> Self {
+        EmbeddingStr::from(Cow::Borrowed(s))
+    }
+}
+
+/// Short content is copied inline and an owned string is freed right away, without any new
+/// allocation. Long content reuses an owned string's buffer, reallocating only to shed spare
+/// capacity.
+impl From<Cow<'_, str>> for EmbeddingStr {
+    #[inline]
+    fn from(s: Cow<'_, str>) -> Self {
+        let len = s.len();
+        if Self::will_embed_len(len) {
+            // an owned `String` is dropped on return
+            Self::new_embedded(&s)
+        } else {
+            Self::new_heap(s.into_owned().into_boxed_str())
+        }
+    }
+}
+
+impl From<Box<str>> for EmbeddingStr {
+    #[inline]
+    fn from(s: Box<str>) -> Self {
+        let len = s.len();
+        if Self::will_embed_len(len) {
+            // the box is dropped on return; its content lives on inline
+            Self::new_embedded(&s)
+        } else {
+            Self::new_heap(s)
+        }
+    }
+}
+
+/// Hands back the boxed allocation as-is, or allocates a new box for embedded content.
+///
+/// The conversion never fails, so `TryFrom<EmbeddingStr>` is available with
+/// [`Infallible`](std::convert::Infallible) as its error.
+impl From<EmbeddingStr> for Box<str> {
+    #[inline]
+    fn from(s: EmbeddingStr) -> Self {
+        s.into_heap().unwrap_or_else(|s| Box::from(s.as_str()))
+    }
+}
+
+/// Reuses the boxed allocation, or allocates a new string for embedded content.
+impl From<EmbeddingStr> for String {
+    #[inline]
+    fn from(s: EmbeddingStr) -> Self {
+        Box::<str>::from(s).into_string()
+    }
+}
+
+impl std::iter::FromIterator<EmbeddingStr> for String {
+    fn from_iter<I: IntoIterator<Item = EmbeddingStr>>(iter: I) -> Self {
+        let mut s = String::new();
+        for item in iter {
+            s.push_str(item.as_str());
+        }
+        s
+    }
+}
+
+/// Error returned by [`EmbeddingStr::from_parts`]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum FromPartsError {
+    /// The embedded length is larger than the embedded buffer
+    TooLong(usize),
+    /// The embedded bytes are not valid UTF-8
+    InvalidUtf8(std::str::Utf8Error),
+}
+
+impl fmt::Display for FromPartsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromPartsError::TooLong(len) => write!(
+                f,
+                "embedded length {} exceeds the maximum of {}",
+                len, MAX_EMBEDDED_LEN
+            ),
+            FromPartsError::InvalidUtf8(e) => {
+                write!(f, "embedded bytes are not valid UTF-8: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FromPartsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FromPartsError::InvalidUtf8(e) => Some(e),
+            FromPartsError::TooLong(_) => None,
+        }
+    }
+}
+
+/// Error returned by [`EmbeddingStr::decode`]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DecodeError {
+    /// The input ends before the length prefix or the bytes it announces
+    Truncated,
+    /// The length prefix doesn't fit in `usize`
+    LengthOverflow,
+    /// The string bytes are not valid UTF-8
+    InvalidUtf8(std::str::Utf8Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => f.write_str("input is truncated"),
+            DecodeError::LengthOverflow => f.write_str("length prefix overflows usize"),
+            DecodeError::InvalidUtf8(e) => write!(f, "string is not valid UTF-8: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::InvalidUtf8(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Display adapter writing the strings of a slice separated by a separator
+///
+/// ```
+/// use embed_str::{DisplayJoin, EmbeddingStr};
+///
+/// let names = [EmbeddingStr::from("a"), EmbeddingStr::from("b")];
+/// assert_eq!(DisplayJoin(&names, ", ").to_string(), "a, b");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayJoin<'a>(pub &'a [EmbeddingStr], pub &'a str);
+
+impl fmt::Display for DisplayJoin<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let DisplayJoin(items, sep) = *self;
+        if let Some((first, rest)) = items.split_first() {
+            f.write_str(first.as_str())?;
+            for item in rest {
+                f.write_str(sep)?;
+                f.write_str(item.as_str())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when converting an [`OsStr`] that is not valid UTF-8
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FromOsStrError(());
+
+impl fmt::Display for FromOsStrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OsStr is not valid UTF-8")
+    }
+}
+
+impl std::error::Error for FromOsStrError {}
+
+impl TryFrom<&'_ OsStr> for EmbeddingStr {
+    type Error = FromOsStrError;
+
+    #[inline]
+    fn try_from(s: &OsStr) -> Result<Self, Self::Error> {
+        s.to_str().map(EmbeddingStr::from).ok_or(FromOsStrError(()))
+    }
+}
+
+/// Common interface of compact string types, for code generic over the string storage
+pub trait CompactStr {
+    fn as_str(&self) -> &str;
+    fn from_str(s: &str) -> Self;
+}
+
+impl CompactStr for EmbeddingStr {
+    #[inline]
+    fn as_str(&self) -> &str {
+        EmbeddingStr::as_str(self)
+    }
+
+    #[inline]
+    fn from_str(s: &str) -> Self {
+        EmbeddingStr::from(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // ascii strings cross the byte-length boundary at the same char count; multibyte ones
+    // hit it in the middle of the range with 2, 3 and 4 byte chars
+    fn any_string() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-z0-9 ]{0,64}",
+            "[é¡ß]{0,32}",
+            "[가-힣]{0,24}",
+            "[😀-🙏]{0,16}",
+            "\\PC{0,64}",
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_round_trip(s in any_string()) {
+            let e = EmbeddingStr::from(s.clone());
+            prop_assert_eq!(e.as_str(), s.as_str());
+            prop_assert_eq!(e.len(), s.len());
+            let expected = if s.len() <= MAX_EMBEDDED_LEN {
+                EmbeddingStrMode::Embedded
+            } else {
+                EmbeddingStrMode::Boxed
+            };
+            prop_assert_eq!(e.mode(), expected);
+            let borrowed = EmbeddingStr::from(s.as_str());
+            prop_assert_eq!(borrowed.as_str(), s.as_str());
+            let boxed = EmbeddingStr::from(s.clone().into_boxed_str());
+            prop_assert_eq!(boxed.as_str(), s.as_str());
+        }
+
+        #[test]
+        fn prop_matches_str_hash_eq_ord(a in any_string(), b in any_string(), same in any::<bool>()) {
+            use std::collections::hash_map::DefaultHasher;
+            use std::hash::{Hash, Hasher};
+            fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+                let mut hasher = DefaultHasher::new();
+                value.hash(&mut hasher);
+                hasher.finish()
+            }
+
+            let b = if same { a.clone() } else { b };
+            let (ea, eb) = (EmbeddingStr::from(a.as_str()), EmbeddingStr::from(b.as_str()));
+            prop_assert_eq!(hash_of(&ea), hash_of(a.as_str()));
+            prop_assert_eq!(hash_of(&eb), hash_of(b.as_str()));
+            prop_assert_eq!(ea == eb, a == b);
+            prop_assert_eq!(ea.cmp(&eb), a.cmp(&b));
+            prop_assert_eq!(ea.partial_cmp(&eb), a.partial_cmp(&b));
+            let borrowed: &str = ea.borrow();
+            prop_assert_eq!(hash_of(borrowed), hash_of(&ea));
+        }
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        assert_eq!(STR_INNER_SIZE, 16);
+
+        let s = EmbeddingStr::from("");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "");
+        let s = EmbeddingStr::from("a");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "a");
+        let s = EmbeddingStr::from("abcdxyz01");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "abcdxyz01");
         let s = EmbeddingStr::from("123456789012345");
         assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
         assert_eq!(s.as_str(), "123456789012345");
@@ -217,4 +1233,1060 @@ mod tests {
         assert_eq!(format!("{}", s), "1234567890123456".to_owned());
         assert_eq!(format!("{:?}", s), "Boxed(\"1234567890123456\")".to_owned());
     }
+
+    #[test]
+    fn test_format_alternate_debug() {
+        let s = EmbeddingStr::from("abc");
+        assert_eq!(format!("{:?}", s), "Embedded(\"abc\")");
+        assert_eq!(
+            format!("{:#?}", s),
+            "EmbeddingStr {\n    mode: Embedded,\n    len: 3,\n    value: \"abc\",\n}"
+        );
+        let s = EmbeddingStr::from("1234567890123456");
+        assert_eq!(
+            format!("{:#?}", s),
+            "EmbeddingStr {\n    mode: Boxed,\n    len: 16,\n    value: \"1234567890123456\",\n}"
+        );
+    }
+
+    #[test]
+    fn test_from_static_embedded() {
+        const EMPTY: EmbeddingStr = EmbeddingStr::from_static_embedded("");
+        const FULL: EmbeddingStr = EmbeddingStr::from_static_embedded("123456789012345");
+        static HANGUL: EmbeddingStr = EmbeddingStr::from_static_embedded("한글");
+        assert_eq!(EMPTY, EmbeddingStr::from(""));
+        assert_eq!(FULL.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(FULL.as_str(), "123456789012345");
+        assert_eq!(HANGUL.as_str(), "한글");
+        assert_eq!(
+            HANGUL.embedded_raw(),
+            EmbeddingStr::from("한글").embedded_raw()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "string literal is too long to be embedded")]
+    fn test_from_static_embedded_too_long() {
+        EmbeddingStr::from_static_embedded("1234567890123456");
+    }
+
+    #[test]
+    fn test_will_embed_len() {
+        const _: () = assert!(EmbeddingStr::will_embed_len(MAX_EMBEDDED_LEN));
+        const _: () = assert!(!EmbeddingStr::will_embed_len(MAX_EMBEDDED_LEN + 1));
+
+        for len in 0..40 {
+            let s = EmbeddingStr::from("x".repeat(len));
+            let expected = if EmbeddingStr::will_embed_len(len) {
+                EmbeddingStrMode::Embedded
+            } else {
+                EmbeddingStrMode::Boxed
+            };
+            assert_eq!(s.mode(), expected);
+        }
+    }
+
+    #[test]
+    fn test_as_str_ref() {
+        fn first_word(s: &mut EmbeddingStr) -> &str {
+            s.as_str_ref().split(' ').next().unwrap()
+        }
+
+        let mut s = EmbeddingStr::from("hello world");
+        let s_mut = &mut s;
+        assert_eq!(s_mut.as_str_ref(), "hello world");
+        assert_eq!(s_mut.as_str(), "hello world");
+        assert_eq!(first_word(s_mut), "hello");
+        let mut s = EmbeddingStr::from("something longer than 15 bytes");
+        assert_eq!(first_word(&mut s), "something");
+    }
+
+    #[test]
+    fn test_embedded_len() {
+        for content in ["", "a", "abcdxyz01", "123456789012345", "한글"] {
+            let s = EmbeddingStr::from(content);
+            assert_eq!(s.embedded_len(), Some(s.len()));
+            assert_eq!(s.len(), content.len());
+        }
+        let s = EmbeddingStr::from("1234567890123456");
+        assert_eq!(s.embedded_len(), None);
+        assert_eq!(s.len(), 16);
+        assert!(!s.is_empty());
+        assert!(EmbeddingStr::from("").is_empty());
+    }
+
+    #[test]
+    fn test_with_embedded_or_else() {
+        let calls = std::cell::Cell::new(0);
+        let fallback = |s: &str| {
+            calls.set(calls.get() + 1);
+            EmbeddingStr::from(s.to_uppercase())
+        };
+        let s = EmbeddingStr::with_embedded_or_else("short", fallback);
+        assert_eq!(s.as_str(), "short");
+        assert_eq!(calls.get(), 0);
+        let s = EmbeddingStr::with_embedded_or_else("something longer than 15 bytes", fallback);
+        assert_eq!(s.as_str(), "SOMETHING LONGER THAN 15 BYTES");
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_from_strings() {
+        let v = [
+            "",
+            "short",
+            "something longer than 15 bytes",
+            "é",
+            "abcdefghijklmnop",
+        ]
+        .iter()
+        .map(|&s| String::from(s))
+        .collect();
+        let converted = EmbeddingStr::from_strings(v);
+        let modes: Vec<_> = converted.iter().map(EmbeddingStr::mode).collect();
+        assert_eq!(
+            modes,
+            [
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Boxed,
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Boxed,
+            ]
+        );
+        assert_eq!(converted[2], "something longer than 15 bytes");
+        assert_eq!(converted[4], "abcdefghijklmnop");
+    }
+
+    #[test]
+    fn test_heap_ptr_round_trip() {
+        let boxed: Box<str> = "something longer than 15 bytes".into();
+        let (ptr, len) = (boxed.as_ptr(), boxed.len());
+        let s = EmbeddingStr::new_heap(boxed);
+        assert_eq!(s.embedded_len(), None);
+        // SAFETY: `s` was just built in heap mode
+        let heap = unsafe { s.heap_ptr() };
+        assert_eq!(heap as *const u8, ptr);
+        assert_eq!(unsafe { &*heap }.len(), len);
+        assert_eq!(s.as_str(), "something longer than 15 bytes");
+    }
+
+    #[test]
+    fn test_from_utf8_vec_unchecked() {
+        let s = unsafe { EmbeddingStr::from_utf8_vec_unchecked("한글".as_bytes().to_vec()) };
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "한글");
+
+        let v = b"something longer than 15 bytes".to_vec();
+        let ptr = v.as_ptr();
+        let s = unsafe { EmbeddingStr::from_utf8_vec_unchecked(v) };
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "something longer than 15 bytes");
+        assert_eq!(s.as_str().as_ptr(), ptr);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "corrupted EmbeddingStr")]
+    fn test_corrupted_discriminant() {
+        // embedded flag set with a length of 127
+        let mut bytes = [0; STR_INNER_SIZE];
+        if cfg!(target_endian = "little") {
+            bytes[0] = 0xff;
+        } else {
+            bytes[STR_INNER_SIZE - 1] = 0xff;
+        }
+        // don't run drop while unwinding; it would hit the assertion again
+        let s = mem::ManuallyDrop::new(EmbeddingStr::from_raw_bytes(bytes));
+        let _ = s.as_str();
+    }
+
+    #[test]
+    fn test_take() {
+        let mut s = EmbeddingStr::from("something longer than 15 bytes");
+        let ptr = s.as_str().as_ptr();
+        let taken = mem::take(&mut s);
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert!(s.is_empty());
+        assert_eq!(taken.as_str().as_ptr(), ptr);
+        assert_eq!(taken, "something longer than 15 bytes");
+        drop(taken);
+
+        let mut s = EmbeddingStr::from("short");
+        assert_eq!(mem::take(&mut s), "short");
+        assert_eq!(s, EmbeddingStr::default());
+    }
+
+    #[test]
+    fn test_into_cow_static() {
+        let s = EmbeddingStr::from("something longer than 15 bytes");
+        let ptr = s.as_str().as_ptr();
+        let cow = s.into_cow_static();
+        assert!(matches!(cow, Cow::Owned(_)));
+        assert_eq!(cow.as_ptr(), ptr);
+        assert_eq!(cow, "something longer than 15 bytes");
+
+        let cow: Cow<'static, str> = EmbeddingStr::from("short").into_cow_static();
+        assert!(matches!(cow, Cow::Owned(_)));
+        assert_eq!(cow, "short");
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let long = "long ".repeat(30);
+        let mut out = Vec::new();
+        for &s in ["", "short", "한글", long.as_str()].iter() {
+            out.clear();
+            EmbeddingStr::from(s).encode(&mut out);
+            assert_eq!(out.len(), s.len() + if s.len() < 0x80 { 1 } else { 2 });
+            out.push(0xaa);
+            let (decoded, rest) = EmbeddingStr::decode(&out).unwrap();
+            assert_eq!(decoded, s);
+            assert_eq!(decoded.mode(), EmbeddingStr::from(s).mode());
+            assert_eq!(rest, [0xaa]);
+        }
+        out.clear();
+        EmbeddingStr::from(long.as_str()).encode(&mut out);
+        assert_eq!(out[..2], [0x96, 0x01]);
+
+        let mut out = Vec::new();
+        EmbeddingStr::from("first").encode(&mut out);
+        EmbeddingStr::from("second").encode(&mut out);
+        let (first, rest) = EmbeddingStr::decode(&out).unwrap();
+        let (second, rest) = EmbeddingStr::decode(rest).unwrap();
+        assert_eq!(
+            (first.as_str(), second.as_str(), rest),
+            ("first", "second", &[][..])
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(EmbeddingStr::decode(&[]), Err(DecodeError::Truncated));
+        assert_eq!(EmbeddingStr::decode(&[0x80]), Err(DecodeError::Truncated));
+        assert_eq!(
+            EmbeddingStr::decode(b"\x05abc"),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            EmbeddingStr::decode(&[0xff; 11]),
+            Err(DecodeError::LengthOverflow)
+        );
+        assert!(matches!(
+            EmbeddingStr::decode(b"\x02\xff\xfe"),
+            Err(DecodeError::InvalidUtf8(_))
+        ));
+    }
+
+    #[test]
+    fn test_parts() {
+        let s = EmbeddingStr::from("abc");
+        let parts = s.into_parts();
+        let mut expected = [0; MAX_EMBEDDED_LEN];
+        expected[..3].copy_from_slice(b"abc");
+        assert_eq!(parts, EmbeddingStrParts::Embedded(expected, 3));
+        let s = EmbeddingStr::from_parts(parts).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "abc");
+
+        let content: Box<str> = "something longer than 15 bytes".into();
+        let ptr = content.as_ptr();
+        let parts = EmbeddingStr::from(content).into_parts();
+        match &parts {
+            EmbeddingStrParts::Boxed(b) => assert_eq!(b.as_ptr(), ptr),
+            EmbeddingStrParts::Embedded(..) => panic!("expected boxed parts"),
+        }
+        let s = EmbeddingStr::from_parts(parts).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "something longer than 15 bytes");
+        assert_eq!(s.as_str().as_ptr(), ptr);
+
+        let s = EmbeddingStr::from_parts(EmbeddingStrParts::Boxed("abc".into())).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+
+        let mut invalid = [0; MAX_EMBEDDED_LEN];
+        invalid[0] = 0xff;
+        assert!(matches!(
+            EmbeddingStr::from_parts(EmbeddingStrParts::Embedded(invalid, 1)),
+            Err(FromPartsError::InvalidUtf8(_))
+        ));
+
+        let full = [b'a'; MAX_EMBEDDED_LEN];
+        let s = EmbeddingStr::from_parts(EmbeddingStrParts::Embedded(full, MAX_EMBEDDED_LEN));
+        assert_eq!(s.unwrap().len(), MAX_EMBEDDED_LEN);
+        let err = EmbeddingStr::from_parts(EmbeddingStrParts::Embedded(full, MAX_EMBEDDED_LEN + 1));
+        assert_eq!(err, Err(FromPartsError::TooLong(MAX_EMBEDDED_LEN + 1)));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            format!(
+                "embedded length {} exceeds the maximum of {}",
+                MAX_EMBEDDED_LEN + 1,
+                MAX_EMBEDDED_LEN
+            )
+        );
+    }
+
+    #[test]
+    fn test_as_ref() {
+        fn shout<S: AsRef<str>>(s: S) -> String {
+            s.as_ref().to_uppercase()
+        }
+        fn checksum<B: AsRef<[u8]>>(b: B) -> u32 {
+            b.as_ref().iter().map(|&b| u32::from(b)).sum()
+        }
+
+        let s = EmbeddingStr::from("abc");
+        assert_eq!(shout(&s), "ABC");
+        assert_eq!(checksum(&s), checksum("abc"));
+        let s = EmbeddingStr::from("something longer than 15 bytes");
+        assert_eq!(shout(&s), "SOMETHING LONGER THAN 15 BYTES");
+        assert_eq!(checksum(&s), checksum("something longer than 15 bytes"));
+        assert_eq!(s.as_bytes(), b"something longer than 15 bytes");
+    }
+
+    #[test]
+    fn test_embedded_raw() {
+        let s = EmbeddingStr::from("abc");
+        let raw = s.embedded_raw().unwrap();
+        let (discriminant, data) = if cfg!(target_endian = "little") {
+            (raw[0], &raw[1..])
+        } else {
+            (raw[STR_INNER_SIZE - 1], &raw[..STR_INNER_SIZE - 1])
+        };
+        assert_eq!(discriminant, (3 << 1) | 1);
+        assert_eq!(&data[..3], b"abc");
+        assert!(data[3..].iter().all(|&b| b == 0));
+
+        let s = EmbeddingStr::from("");
+        assert_eq!(
+            s.embedded_raw()
+                .unwrap()
+                .iter()
+                .filter(|&&b| b != 0)
+                .count(),
+            1
+        );
+        assert!(EmbeddingStr::from("something longer than 15 bytes")
+            .embedded_raw()
+            .is_none());
+    }
+
+    #[test]
+    fn test_common_prefix_len() {
+        let cases = [
+            ("abc", "abc", 3),
+            ("abc", "xyz", 0),
+            ("ab", "abc", 2),
+            ("abc", "ab", 2),
+            ("", "abc", 0),
+            ("abcd", "abXd", 2),
+            // stops inside the second char, which shares its first byte
+            ("한글", "한국", 4),
+            (
+                "something longer than 15 bytes",
+                "something longer than 15 bytes",
+                30,
+            ),
+            ("something longer than 15 bytes", "something longer", 16),
+            ("something", "something longer than 15 bytes", 9),
+            ("something longer", "something else entirely", 10),
+            ("xyz", "something longer than 15 bytes", 0),
+        ];
+        for (a, b, expected) in cases.iter() {
+            let (a, b) = (EmbeddingStr::from(*a), EmbeddingStr::from(*b));
+            assert_eq!(a.common_prefix_len(&b), *expected, "{:?} {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_find_any() {
+        let delims = [' ', ',', '、'];
+        let s = EmbeddingStr::from("a,b c");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.find_any(&delims), Some(1));
+        assert_eq!(s.find_any(&[' ']), Some(3));
+
+        let s = EmbeddingStr::from("한글、and then some more text");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.find_any(&delims), Some(6));
+
+        assert_eq!(EmbeddingStr::from("abc").find_any(&delims), None);
+        assert_eq!(
+            EmbeddingStr::from("no-delimiters-at-all").find_any(&delims),
+            None
+        );
+        assert_eq!(EmbeddingStr::from("a b").find_any(&[]), None);
+    }
+
+    #[test]
+    fn test_count_char_find() {
+        let long = format!("{}é{}", "a,b;c ".repeat(50), "x,y".repeat(30));
+        let contents = ["", "a,b,c", "é,é", long.as_str()];
+        for &content in contents.iter() {
+            let s = EmbeddingStr::from(content);
+            for &c in [',', ';', 'é', 'x', 'z', '\0'].iter() {
+                assert_eq!(s.count_char(c), content.matches(c).count(), "{:?}", c);
+                assert_eq!(s.find(c), content.find(c), "{:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_char_at_byte() {
+        let s = EmbeddingStr::from("a한b");
+        assert_eq!(s.char_at_byte(0), Some('a'));
+        assert_eq!(s.char_at_byte(1), Some('한'));
+        assert_eq!(s.char_at_byte(2), None);
+        assert_eq!(s.char_at_byte(3), None);
+        assert_eq!(s.char_at_byte(4), Some('b'));
+        assert_eq!(s.char_at_byte(5), None);
+        assert_eq!(s.char_at_byte(usize::MAX), None);
+
+        let s = EmbeddingStr::from("한글 is longer than fifteen bytes");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.char_at_byte(3), Some('글'));
+        assert_eq!(s.char_at_byte(5), None);
+        assert_eq!(s.char_at_byte(6), Some(' '));
+    }
+
+    #[test]
+    fn test_starts_with_bytes() {
+        let s = EmbeddingStr::from("GIF89a");
+        assert!(s.starts_with_bytes(b""));
+        assert!(s.starts_with_bytes(b"GIF"));
+        assert!(s.starts_with_bytes(b"GIF89a"));
+        assert!(!s.starts_with_bytes(b"gif"));
+        assert!(!s.starts_with_bytes(b"GIF89a\0"));
+        assert!(!s.starts_with_bytes(&[b'G', b'I', b'F', b'8', b'9', b'a', 0, 0]));
+        assert!(EmbeddingStr::from("한").starts_with_bytes(&[0xed]));
+
+        let s = EmbeddingStr::from("%PDF-1.7 and some more bytes");
+        assert!(s.starts_with_bytes(b"%PDF"));
+        assert!(!s.starts_with_bytes(b"%PS"));
+        assert!(!s.starts_with_bytes("%PDF-1.7 and some more bytes!".as_bytes()));
+    }
+
+    #[test]
+    fn test_ends_with_ignore_ascii_case() {
+        let s = EmbeddingStr::from("photo.JPG");
+        assert!(s.ends_with_ignore_ascii_case(".jpg"));
+        assert!(s.ends_with_ignore_ascii_case(".JpG"));
+        assert!(s.ends_with_ignore_ascii_case(""));
+        assert!(!s.ends_with_ignore_ascii_case(".png"));
+        assert!(!s.ends_with_ignore_ascii_case("a longer suffix.jpg"));
+        let s = EmbeddingStr::from("Some/Long/Path/To/README.Md");
+        assert!(s.ends_with_ignore_ascii_case("readme.md"));
+        assert!(!s.ends_with_ignore_ascii_case("readme.mdx"));
+        // only ASCII letters fold
+        assert!(!EmbeddingStr::from("ÉCOLE").ends_with_ignore_ascii_case("école"));
+    }
+
+    #[test]
+    fn test_nth_char() {
+        let s = EmbeddingStr::from("abc");
+        assert_eq!(s.nth_char(0), Some('a'));
+        assert_eq!(s.nth_char(2), Some('c'));
+        assert_eq!(s.nth_char(3), None);
+        let s = EmbeddingStr::from("añ한😀z");
+        assert_eq!(s.nth_char(1), Some('ñ'));
+        assert_eq!(s.nth_char(2), Some('한'));
+        assert_eq!(s.nth_char(3), Some('😀'));
+        assert_eq!(s.nth_char(4), Some('z'));
+        assert_eq!(s.nth_char(5), None);
+        let s = EmbeddingStr::from("something longer than 15 bytes");
+        assert_eq!(s.nth_char(29), Some('s'));
+        assert_eq!(s.nth_char(30), None);
+    }
+
+    #[test]
+    fn test_byte_at() {
+        let s = EmbeddingStr::from("abc");
+        assert_eq!(s.byte_at(0), Some(b'a'));
+        assert_eq!(s.byte_at(2), Some(b'c'));
+        assert_eq!(s.byte_at(3), None);
+        assert_eq!(s.byte_at(usize::MAX), None);
+        let s = EmbeddingStr::from("something longer than 15 bytes");
+        assert_eq!(s.byte_at(0), Some(b's'));
+        assert_eq!(s.byte_at(29), Some(b's'));
+        assert_eq!(s.byte_at(30), None);
+        let s = EmbeddingStr::from("é");
+        assert_eq!(s.byte_at(1), Some(0xa9));
+    }
+
+    #[test]
+    fn test_into_owned() {
+        let s: Box<str> = EmbeddingStr::from("abc").into();
+        assert_eq!(&*s, "abc");
+        let s: String = EmbeddingStr::from("abc").into();
+        assert_eq!(s, "abc");
+
+        let content: Box<str> = "something longer than 15 bytes".into();
+        let ptr = content.as_ptr();
+        let boxed: Box<str> = EmbeddingStr::from(content).into();
+        assert_eq!(boxed.as_ptr(), ptr);
+        let string: String = EmbeddingStr::from(boxed).into();
+        assert_eq!(string.as_ptr(), ptr);
+        let boxed = Box::<str>::from(EmbeddingStr::from(string));
+        assert_eq!(boxed.as_ptr(), ptr);
+        assert_eq!(&*boxed, "something longer than 15 bytes");
+    }
+
+    #[test]
+    fn test_collect_string() {
+        let values = vec![
+            EmbeddingStr::from("short "),
+            EmbeddingStr::from("and something longer than 15 bytes"),
+            EmbeddingStr::from(" end"),
+        ];
+        let s: String = values.into_iter().collect();
+        assert_eq!(s, "short and something longer than 15 bytes end");
+        let s: String = Vec::<EmbeddingStr>::new().into_iter().collect();
+        assert_eq!(s, "");
+    }
+
+    #[test]
+    fn test_clone() {
+        let s = EmbeddingStr::from("abc");
+        let cloned = s.clone();
+        drop(s);
+        assert_eq!(cloned.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(cloned.as_str(), "abc");
+
+        let s = EmbeddingStr::from("something longer than 15 bytes");
+        let cloned = s.clone();
+        assert_ne!(s.as_str().as_ptr(), cloned.as_str().as_ptr());
+        drop(s);
+        assert_eq!(cloned.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(cloned.as_str(), "something longer than 15 bytes");
+        let cloned_again = cloned.clone();
+        drop(cloned);
+        assert_eq!(cloned_again.as_str(), "something longer than 15 bytes");
+    }
+
+    #[test]
+    fn test_trim_matches_owned() {
+        let s = EmbeddingStr::from("//usr/local/share/doc//");
+        let trimmed = s.trim_matches_owned('/');
+        assert_eq!(trimmed.as_str(), "usr/local/share/doc");
+        assert_eq!(trimmed.mode(), EmbeddingStrMode::Boxed);
+        let trimmed = EmbeddingStr::from("/usr/local/share/").trim_matches_owned('/');
+        assert_eq!(trimmed.as_str(), "usr/local/share");
+        assert_eq!(trimmed.mode(), EmbeddingStrMode::Embedded);
+        let trimmed = EmbeddingStr::from("no-slashes").trim_matches_owned('/');
+        assert_eq!(trimmed.as_str(), "no-slashes");
+
+        let trimmed = EmbeddingStr::from("////////////////////").trim_matches_owned('/');
+        assert_eq!(trimmed.as_str(), "");
+        assert_eq!(trimmed.mode(), EmbeddingStrMode::Embedded);
+    }
+
+    #[test]
+    fn test_slicing() {
+        let s = EmbeddingStr::from("héllo wörld, this is long");
+        assert_eq!(&s[..1], "h");
+        assert_eq!(&s[1..3], "é");
+        assert_eq!(&s[7..=9], "wö");
+        assert_eq!(&s[..], s.as_str());
+        assert_eq!(s.substr(..6).as_str(), "héllo");
+        assert_eq!(s.substr(..6).mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.substr(7..).as_str(), "wörld, this is long");
+        assert_eq!(s.substr(7..).mode(), EmbeddingStrMode::Boxed);
+        let (left, right) = s.split_at(6);
+        assert_eq!(left.as_str(), "héllo");
+        assert_eq!(right.as_str(), " wörld, this is long");
+        let (left, right) = s.split_at(s.len());
+        assert_eq!(left.as_str(), s.as_str());
+        assert_eq!(right.as_str(), "");
+    }
+
+    #[test]
+    fn test_replace_char() {
+        let s = EmbeddingStr::from("a/b/c").replace_char('/', '.');
+        assert_eq!(s, "a.b.c");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(EmbeddingStr::from("한/글").replace_char('/', '_'), "한_글");
+        assert_eq!(EmbeddingStr::from("").replace_char('a', 'b'), "");
+
+        let s = EmbeddingStr::from("some/long/path/to/a/file").replace_char('/', '\\');
+        assert_eq!(s, "some\\long\\path\\to\\a\\file");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+
+        // a longer replacement can move the result out of the embedded buffer
+        let s = EmbeddingStr::from("a-b-c-d-e-f").replace_char('-', '→');
+        assert_eq!(s, "a→b→c→d→e→f");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        let s = EmbeddingStr::from("ＡＡ tail ends");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        let s = s.replace_char('Ａ', 'A');
+        assert_eq!(s, "AA tail ends");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+    }
+
+    #[test]
+    fn test_rsplit_once_owned() {
+        let (stem, ext) = EmbeddingStr::from("file.txt")
+            .rsplit_once_owned('.')
+            .unwrap();
+        assert_eq!((stem.as_str(), ext.as_str()), ("file", "txt"));
+        assert_eq!(stem.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(ext.mode(), EmbeddingStrMode::Embedded);
+
+        let (stem, ext) = EmbeddingStr::from("archive.of.some.long.name.tar.gz")
+            .rsplit_once_owned('.')
+            .unwrap();
+        assert_eq!(
+            (stem.as_str(), ext.as_str()),
+            ("archive.of.some.long.name.tar", "gz")
+        );
+        assert_eq!(stem.mode(), EmbeddingStrMode::Boxed);
+
+        let (stem, ext) = EmbeddingStr::from("한.글").rsplit_once_owned('.').unwrap();
+        assert_eq!((stem.as_str(), ext.as_str()), ("한", "글"));
+        assert_eq!(EmbeddingStr::from("Makefile").rsplit_once_owned('.'), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 2 is not a char boundary")]
+    fn test_index_not_char_boundary() {
+        let s = EmbeddingStr::from("héllo");
+        let _ = &s[..2];
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 2 is not a char boundary")]
+    fn test_substr_not_char_boundary() {
+        EmbeddingStr::from("héllo").substr(2..);
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 2 is not a char boundary")]
+    fn test_split_at_not_char_boundary() {
+        EmbeddingStr::from("héllo").split_at(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 6 is out of bounds of string of length 5")]
+    fn test_index_out_of_bounds() {
+        let s = EmbeddingStr::from("hello");
+        let _ = &s[..6];
+    }
+
+    #[test]
+    fn test_lines_owned() {
+        let s =
+            EmbeddingStr::from("GET /\nPOST /login\r\n\na log line longer than the limit\nend\n");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        let lines: Vec<_> = s.lines_owned().collect();
+        let expected = [
+            "GET /",
+            "POST /login",
+            "",
+            "a log line longer than the limit",
+            "end",
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for (line, expected) in lines.iter().zip(expected.iter()) {
+            assert_eq!(line.as_str(), *expected);
+        }
+        let modes: Vec<_> = lines.iter().map(|line| line.mode()).collect();
+        assert_eq!(
+            modes,
+            [
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Embedded,
+                EmbeddingStrMode::Boxed,
+                EmbeddingStrMode::Embedded,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_whitespace_only() {
+        let long_blank = " \t".repeat(20);
+        let long_text = format!("{}x", long_blank);
+        for &(content, expected) in [
+            ("", true),
+            ("    ", true),
+            (" \t\n\x0B\x0C\r", true),
+            (" a ", false),
+            ("\u{3000}", true),
+            (" \u{3000}\u{a0}", true),
+            (" \u{3000}x", false),
+            (long_blank.as_str(), true),
+            (long_text.as_str(), false),
+        ]
+        .iter()
+        {
+            let s = EmbeddingStr::from(content);
+            assert_eq!(s.is_whitespace_only(), expected, "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn test_eq_shared_pointer() {
+        let s = EmbeddingStr::from("long enough to be put on the heap");
+        let shared = std::mem::ManuallyDrop::new(EmbeddingStr::from_raw_bytes(s.0));
+        assert_eq!(*shared, s);
+        assert_ne!(s, EmbeddingStr::from("long enough to be put on the heap!"));
+
+        // a poisoned allocation that would fault if the bytes were ever read
+        let addr = std::ptr::NonNull::<u8>::dangling().as_ptr() as usize;
+        let raw = layout::encode_heap(addr, 1 << 20, Endian::NATIVE);
+        let a = std::mem::ManuallyDrop::new(EmbeddingStr::from_raw_bytes(raw));
+        let b = std::mem::ManuallyDrop::new(EmbeddingStr::from_raw_bytes(raw));
+        assert!(*a == *b);
+    }
+
+    #[test]
+    fn test_count_lines() {
+        for content in [
+            "",
+            "\n",
+            "\n\n",
+            "a",
+            "a\n",
+            "a\nb",
+            "a\r\nb\r\n",
+            "a\n\nb\n",
+            "line one\nline two\nline three",
+            "line one\nline two\nline three\n",
+        ] {
+            let s = EmbeddingStr::from(content);
+            assert_eq!(s.count_lines(), content.lines().count(), "{:?}", content);
+        }
+    }
+
+    #[test]
+    fn test_justify() {
+        // expected values are from CPython
+        let s = EmbeddingStr::from("abc");
+        assert_eq!(s.ljust(6, '*').as_str(), "abc***");
+        assert_eq!(s.rjust(6, '*').as_str(), "***abc");
+        assert_eq!(s.ljust(3, '*').as_str(), "abc");
+        assert_eq!(s.rjust(3, '*').as_str(), "abc");
+        assert_eq!(s.ljust(2, '*').as_str(), "abc");
+        assert_eq!(s.rjust(0, '*').as_str(), "abc");
+
+        // width counts chars, not bytes
+        let s = EmbeddingStr::from("한글");
+        assert_eq!(s.ljust(4, ' ').as_str(), "한글  ");
+        assert_eq!(s.rjust(4, '·').as_str(), "··한글");
+        assert_eq!(s.rjust(2, '·').as_str(), "한글");
+
+        let s = EmbeddingStr::from("abc").rjust(20, '0');
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "00000000000000000abc");
+    }
+
+    #[test]
+    fn test_constructors_agree_on_len() {
+        for len in [
+            0,
+            1,
+            MAX_EMBEDDED_LEN - 1,
+            MAX_EMBEDDED_LEN,
+            MAX_EMBEDDED_LEN + 1,
+            64,
+        ] {
+            let content = "x".repeat(len);
+            let expected = EmbeddingStr::from(content.as_str());
+            let built = [
+                EmbeddingStr::from(Box::<str>::from(content.as_str())),
+                EmbeddingStr::from(Cow::Owned(content.clone())),
+                EmbeddingStr::from(Cow::Borrowed(content.as_str())),
+                EmbeddingStr::from("x").repeat(len),
+                EmbeddingStr::concat(&[
+                    EmbeddingStr::from(&content[..len / 2]),
+                    EmbeddingStr::from(&content[len / 2..]),
+                ]),
+            ];
+            for s in built.iter() {
+                assert_eq!(s, &expected, "{}", len);
+                assert_eq!(s.len(), len);
+                assert_eq!(s.mode(), expected.mode(), "{}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_concat_join_repeat() {
+        let parts: Vec<EmbeddingStr> = ["ab", "cd", "ef"]
+            .iter()
+            .copied()
+            .map(EmbeddingStr::from)
+            .collect();
+        let s = EmbeddingStr::concat(&parts);
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "abcdef");
+        let s = EmbeddingStr::join(&parts, ", ");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "ab, cd, ef");
+        let s = EmbeddingStr::join(&parts, " and then ");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "ab and then cd and then ef");
+        assert_eq!(EmbeddingStr::concat(&[]).as_str(), "");
+        assert_eq!(EmbeddingStr::join(&parts[..1], ", ").as_str(), "ab");
+
+        let s = EmbeddingStr::from("ab");
+        assert_eq!(s.repeat(0).as_str(), "");
+        assert_eq!(s.repeat(7).mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.repeat(7).as_str(), "ababababababab");
+        assert_eq!(s.repeat(8).mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.repeat(8).as_str(), "abababababababab");
+    }
+
+    #[test]
+    fn test_join_iter() {
+        let tokens = ["ab", "cd", "ef"].iter().copied().map(EmbeddingStr::from);
+        let s = EmbeddingStr::join_iter(tokens, "-");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "ab-cd-ef");
+
+        let tokens = ["alpha", "beta", "gamma"]
+            .iter()
+            .copied()
+            .map(EmbeddingStr::from);
+        let s = EmbeddingStr::join_iter(tokens, ", ");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "alpha, beta, gamma");
+
+        assert_eq!(EmbeddingStr::join_iter(Vec::new(), ", ").as_str(), "");
+    }
+
+    #[test]
+    fn test_repeat_empty_huge() {
+        assert_eq!(EmbeddingStr::from("").repeat(usize::MAX), "");
+        assert_eq!(
+            EmbeddingStr::from("").repeat(1 << 40).mode(),
+            EmbeddingStrMode::Embedded
+        );
+        assert_eq!(EmbeddingStr::from("ab").repeat(0), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "EmbeddingStr length overflows usize")]
+    fn test_repeat_overflow() {
+        EmbeddingStr::from("ab").repeat(usize::MAX / 2 + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "EmbeddingStr length overflows usize")]
+    fn test_justify_overflow() {
+        EmbeddingStr::from("a").ljust(usize::MAX, 'é');
+    }
+
+    #[test]
+    fn test_eq_str() {
+        let s = EmbeddingStr::from("foo");
+        assert!(s == "foo");
+        assert!(s == *"foo");
+        assert!(s != "bar");
+        let kind = match s {
+            ref s if s == "bar" => 0,
+            ref s if s == "foo" => 1,
+            _ => 2,
+        };
+        assert_eq!(kind, 1);
+
+        let names = ["bar", "foo", "something longer than 15 bytes"];
+        let long = EmbeddingStr::from("something longer than 15 bytes");
+        assert_eq!(names.iter().position(|name| s == name), Some(1));
+        assert_eq!(names.iter().position(|name| long == name), Some(2));
+        assert!(names.iter().any(|name| matches!(&long, l if l == name)));
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::hash::{Hash, Hasher};
+
+        #[derive(Default)]
+        struct RecordingHasher(Vec<Vec<u8>>);
+
+        impl Hasher for RecordingHasher {
+            fn finish(&self) -> u64 {
+                0
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                self.0.push(bytes.to_vec());
+            }
+        }
+
+        for &content in ["", "abc", "한글", "something longer than 15 bytes"].iter() {
+            let mut expected = RecordingHasher::default();
+            content.hash(&mut expected);
+            let mut actual = RecordingHasher::default();
+            EmbeddingStr::from(content).hash(&mut actual);
+            assert_eq!(actual.0, expected.0);
+            assert_eq!(actual.0.last(), Some(&vec![0xff]));
+        }
+    }
+
+    #[test]
+    fn test_display_join() {
+        let items = [
+            EmbeddingStr::from("one"),
+            EmbeddingStr::from("a much longer second item"),
+            EmbeddingStr::from(""),
+            EmbeddingStr::from("four"),
+        ];
+        assert_eq!(
+            format!("[{}]", DisplayJoin(&items, ", ")),
+            "[one, a much longer second item, , four]"
+        );
+        assert_eq!(DisplayJoin(&items[..1], ", ").to_string(), "one");
+        assert_eq!(DisplayJoin(&[], ", ").to_string(), "");
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        let mut v: Vec<EmbeddingStr> = [
+            "pear",
+            "a string longer than the limit",
+            "apple",
+            "pear",
+            "a string longer than the limit",
+            "banana",
+            "apple",
+            "",
+        ]
+        .iter()
+        .copied()
+        .map(EmbeddingStr::from)
+        .collect();
+        dedup_sorted(&mut v);
+        let v: Vec<&str> = v.iter().map(EmbeddingStr::as_str).collect();
+        assert_eq!(
+            v,
+            [
+                "",
+                "a string longer than the limit",
+                "apple",
+                "banana",
+                "pear"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_from_os_str() {
+        let s = EmbeddingStr::try_from(OsStr::new("file.txt")).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "file.txt");
+        let s = EmbeddingStr::try_from(OsStr::new("/a/path/longer/than/limit")).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "/a/path/longer/than/limit");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_try_from_os_str_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let os = OsStr::from_bytes(b"invalid\xff");
+        assert_eq!(EmbeddingStr::try_from(os), Err(FromOsStrError(())));
+    }
+
+    #[test]
+    fn test_compact_str() {
+        fn shout<S: CompactStr>(s: &S) -> S {
+            S::from_str(&s.as_str().to_uppercase())
+        }
+
+        let s = shout(&<EmbeddingStr as CompactStr>::from_str("hello"));
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(CompactStr::as_str(&s), "HELLO");
+        let s = shout(&<EmbeddingStr as CompactStr>::from_str("hello, long world"));
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(CompactStr::as_str(&s), "HELLO, LONG WORLD");
+    }
+
+    #[test]
+    fn test_from_fmt() {
+        let s = EmbeddingStr::from(format_args!("x={}", 5));
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "x=5");
+        let s = EmbeddingStr::from(format_args!("{:015}", 7));
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "000000000000007");
+        let s = EmbeddingStr::from(format_args!("{}: {}, {}", "key", "value", 1234567));
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "key: value, 1234567");
+        let s = EmbeddingStr::from_fmt(format_args!("{}{}", "12345678", "abcdefgh"));
+        assert_eq!(s.as_str(), "12345678abcdefgh");
+    }
+
+    #[test]
+    fn test_range_str() {
+        let set: BTreeSet<EmbeddingStr> = [
+            "apple",
+            "apricot and a long description",
+            "banana",
+            "blueberry with a long description",
+            "cherry",
+            "b",
+        ]
+        .iter()
+        .copied()
+        .map(EmbeddingStr::from)
+        .collect();
+        assert!(set.contains("banana"));
+        assert!(set.contains("apricot and a long description"));
+
+        let found: Vec<&str> = range_str(&set, "ap", "c")
+            .map(EmbeddingStr::as_str)
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "apple",
+                "apricot and a long description",
+                "b",
+                "banana",
+                "blueberry with a long description"
+            ]
+        );
+        let found: Vec<&str> = range_str(&set, "b", "banana")
+            .map(EmbeddingStr::as_str)
+            .collect();
+        assert_eq!(found, ["b"]);
+        assert_eq!(range_str(&set, "x", "z").count(), 0);
+    }
+
+    #[cfg(feature = "rustc-hash")]
+    #[test]
+    fn test_fx_map_and_set() {
+        let mut map = EmbeddingStrMap::default();
+        map.insert(EmbeddingStr::from("short"), 1);
+        map.insert(EmbeddingStr::from("a key long enough to be boxed"), 2);
+        assert_eq!(map.get("short"), Some(&1));
+        assert_eq!(map.get("a key long enough to be boxed"), Some(&2));
+        assert_eq!(map.get("missing"), None);
+        *map.get_mut("short").unwrap() += 10;
+        assert_eq!(map["short"], 11);
+
+        let set: EmbeddingStrSet = ["a", "b", "a"]
+            .iter()
+            .copied()
+            .map(EmbeddingStr::from)
+            .collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains("a"));
+        assert!(!set.contains("c"));
+    }
+
+    #[test]
+    fn test_try_embed_fmt() {
+        let s = EmbeddingStr::try_embed_fmt(format_args!("{}-{}", "ab", 42)).unwrap();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "ab-42");
+        let s = EmbeddingStr::try_embed_fmt(format_args!("{:015}", 7)).unwrap();
+        assert_eq!(s.as_str(), "000000000000007");
+
+        assert!(EmbeddingStr::try_embed_fmt(format_args!("{:016}", 7)).is_none());
+        assert!(
+            EmbeddingStr::try_embed_fmt(format_args!("{}{}", "12345678", "abcdefgh")).is_none()
+        );
+    }
 }
@@ -0,0 +1,145 @@
+//! Recycling of heap buffers for boxed `EmbeddingStr`
+
+use crate::EmbeddingStr;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Pool of heap buffers for boxed [`EmbeddingStr`] content
+///
+/// A boxed str is deallocated with its exact length, so a buffer is only reused for a string
+/// of exactly the same byte length; strings that are merely of similar size never share
+/// buffers. This pays off for fixed-width data such as formatted ids or timestamps, not for
+/// arbitrary text.
+///
+/// Values that are handed back with [`recycle`](Self::recycle) return their buffer to the
+/// pool, up to a fixed number of idle buffers per length; the rest are freed, as are values
+/// dropped the normal way. Short strings are embedded and never touch the pool.
+pub struct StrPool {
+    buffers: RefCell<HashMap<usize, Vec<Box<[u8]>>>>,
+    max_per_len: usize,
+}
+
+impl Default for StrPool {
+    fn default() -> Self {
+        Self::with_max_per_len(Self::DEFAULT_MAX_PER_LEN)
+    }
+}
+
+impl StrPool {
+    /// Idle buffers kept per length by [`new`](Self::new).
+    pub const DEFAULT_MAX_PER_LEN: usize = 16;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a pool keeping at most `max_per_len` idle buffers of each length.
+    pub fn with_max_per_len(max_per_len: usize) -> Self {
+        Self {
+            buffers: RefCell::default(),
+            max_per_len,
+        }
+    }
+
+    /// Create a new value, reusing a recycled buffer of the same length if there is one.
+    pub fn alloc(&self, s: &str) -> EmbeddingStr {
+        if EmbeddingStr::will_embed_len(s.len()) {
+            return EmbeddingStr::from(s);
+        }
+        let recycled = self
+            .buffers
+            .borrow_mut()
+            .get_mut(&s.len())
+            .and_then(Vec::pop);
+        match recycled {
+            Some(mut buf) => {
+                buf.copy_from_slice(s.as_bytes());
+                // SAFETY: the whole buffer was just overwritten with `s`
+                EmbeddingStr::from(unsafe { std::str::from_boxed_utf8_unchecked(buf) })
+            }
+            None => EmbeddingStr::from(Box::<str>::from(s)),
+        }
+    }
+
+    /// Drop `s`, keeping its heap buffer for later [`alloc`](Self::alloc) calls unless the
+    /// pool already holds the maximum number of idle buffers of that length.
+    pub fn recycle(&self, s: EmbeddingStr) {
+        if let Ok(b) = s.into_heap() {
+            let mut buffers = self.buffers.borrow_mut();
+            let bucket = buffers.entry(b.len()).or_default();
+            if bucket.len() < self.max_per_len {
+                bucket.push(b.into_boxed_bytes());
+            }
+        }
+    }
+
+    /// Number of buffers waiting to be reused.
+    pub fn idle_buffers(&self) -> usize {
+        self.buffers.borrow().values().map(Vec::len).sum()
+    }
+
+    /// Free all idle buffers.
+    pub fn clear(&self) {
+        self.buffers.borrow_mut().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmbeddingStrMode;
+
+    #[test]
+    fn test_reuse() {
+        let pool = StrPool::new();
+        let s = pool.alloc("something longer than 15 bytes");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        let ptr = s.as_str().as_ptr();
+        pool.recycle(s);
+        assert_eq!(pool.idle_buffers(), 1);
+
+        // a different length doesn't fit the recycled buffer
+        let other = pool.alloc("something else longer than 15 bytes");
+        assert_ne!(other.as_str().as_ptr(), ptr);
+        assert_eq!(pool.idle_buffers(), 1);
+
+        let s = pool.alloc("the same length as before, too");
+        assert_eq!(s.as_str(), "the same length as before, too");
+        assert_eq!(s.as_str().as_ptr(), ptr);
+        assert_eq!(pool.idle_buffers(), 0);
+
+        pool.recycle(s);
+        pool.recycle(other);
+        assert_eq!(pool.idle_buffers(), 2);
+        pool.clear();
+        assert_eq!(pool.idle_buffers(), 0);
+    }
+
+    #[test]
+    fn test_bucket_cap() {
+        let pool = StrPool::with_max_per_len(2);
+        let values: Vec<_> = (0..4)
+            .map(|i| pool.alloc(&format!("fixed width value {:03}", i)))
+            .collect();
+        values.into_iter().for_each(|s| pool.recycle(s));
+        assert_eq!(pool.idle_buffers(), 2);
+
+        pool.recycle(pool.alloc("a value of another length"));
+        assert_eq!(pool.idle_buffers(), 3);
+
+        let pool = StrPool::new();
+        for _ in 0..StrPool::DEFAULT_MAX_PER_LEN + 1 {
+            pool.recycle(EmbeddingStr::from("something longer than 15 bytes"));
+        }
+        assert_eq!(pool.idle_buffers(), StrPool::DEFAULT_MAX_PER_LEN);
+    }
+
+    #[test]
+    fn test_short() {
+        let pool = StrPool::new();
+        let s = pool.alloc("short");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        pool.recycle(s);
+        assert_eq!(pool.idle_buffers(), 0);
+    }
+}
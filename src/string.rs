@@ -0,0 +1,299 @@
+//! Growable counterpart of `EmbeddingStr`
+
+use crate::{check_range, EmbeddedBuf, EmbeddingStr, EmbeddingStrMode, SpillBuf, MAX_EMBEDDED_LEN};
+use std::ops::RangeBounds;
+
+/// Growable string that keeps short content inline like [`EmbeddingStr`]
+///
+/// Content moves to a heap `String` once it outgrows the embedded size. It moves back inline
+/// when [`replace_range`](Self::replace_range) or [`shrink_to_fit`](Self::shrink_to_fit)
+/// leave it short enough; other shrinking methods keep the heap buffer.
+pub struct EmbeddingString(SpillBuf);
+
+impl EmbeddingString {
+    pub fn new() -> Self {
+        Self(SpillBuf::new())
+    }
+
+    pub fn mode(&self) -> EmbeddingStrMode {
+        match self.0 {
+            SpillBuf::Embedded(_) => EmbeddingStrMode::Embedded,
+            SpillBuf::Heap(_) => EmbeddingStrMode::Boxed,
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.as_str().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes that fit without reallocating; the embedded size when not on the heap.
+    pub fn capacity(&self) -> usize {
+        match &self.0 {
+            SpillBuf::Embedded(_) => MAX_EMBEDDED_LEN,
+            SpillBuf::Heap(s) => s.capacity(),
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.0.push_str(s)
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Shorten to `new_len` bytes; does nothing if already shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_len` is not on a char boundary.
+    #[track_caller]
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len() {
+            return;
+        }
+        check_range(self.as_str(), ..new_len);
+        match &mut self.0 {
+            SpillBuf::Embedded(buf) => buf.truncate(new_len),
+            SpillBuf::Heap(s) => s.truncate(new_len),
+        }
+    }
+
+    /// Replace the byte `range` with `replacement`, like `String::replace_range`.
+    ///
+    /// Embedded content spills to the heap if it grows past the embedded size, and heap
+    /// content is re-embedded if it shrinks into it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or doesn't lie on char boundaries.
+    #[track_caller]
+    pub fn replace_range(&mut self, range: impl RangeBounds<usize>, replacement: &str) {
+        let range = check_range(self.as_str(), range);
+        match &mut self.0 {
+            SpillBuf::Embedded(buf) => {
+                let current = buf.as_str();
+                let mut new = SpillBuf::new();
+                new.push_str(&current[..range.start]);
+                new.push_str(replacement);
+                new.push_str(&current[range.end..]);
+                self.0 = new;
+            }
+            SpillBuf::Heap(s) => {
+                s.replace_range(range, replacement);
+                if EmbeddingStr::will_embed_len(s.len()) {
+                    self.shrink_to_fit();
+                }
+            }
+        }
+    }
+
+    /// Minimize memory use: content that fits inline is re-embedded and its heap buffer
+    /// freed, otherwise the heap buffer is shrunk to the content length.
+    pub fn shrink_to_fit(&mut self) {
+        if let SpillBuf::Heap(s) = &mut self.0 {
+            if EmbeddingStr::will_embed_len(s.len()) {
+                let mut buf = EmbeddedBuf::new();
+                buf.push_str(s).expect("content fits inline");
+                self.0 = SpillBuf::Embedded(buf);
+            } else {
+                s.shrink_to_fit();
+            }
+        }
+    }
+}
+
+impl EmbeddingString {
+    /// Convert into an immutable value, embedding the content if it is short.
+    ///
+    /// Heap content keeps its buffer, shrunk to fit.
+    pub fn into_immutable(self) -> EmbeddingStr {
+        self.0.into_embedding_str()
+    }
+}
+
+impl EmbeddingStr {
+    /// Convert into a growable [`EmbeddingString`], reusing the heap allocation if boxed.
+    pub fn into_mut(self) -> EmbeddingString {
+        match self.into_heap() {
+            Ok(boxed) => EmbeddingString(SpillBuf::Heap(boxed.into_string())),
+            Err(s) => {
+                let mut new = EmbeddedBuf::new();
+                new.push_str(s.as_str())
+                    .expect("embedded content fits inline");
+                EmbeddingString(SpillBuf::Embedded(new))
+            }
+        }
+    }
+}
+
+impl Default for EmbeddingString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<&'_ str> for EmbeddingString {
+    fn from(s: &str) -> Self {
+        let mut new = Self::new();
+        new.push_str(s);
+        new
+    }
+}
+
+impl std::fmt::Display for EmbeddingString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl std::fmt::Debug for EmbeddingString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}({:?})", self.mode(), self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut s = EmbeddingString::new();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        s.push_str("hello");
+        s.push(',');
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "hello,");
+        s.push_str(" world!!!");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.len(), 15);
+        s.push('한');
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "hello, world!!!한");
+        assert_eq!(format!("{:?}", s), "Boxed(\"hello, world!!!한\")");
+    }
+
+    #[test]
+    fn test_shrink_to_fit_re_embeds() {
+        let mut s = EmbeddingString::from("a value longer than the limit");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        s.truncate(7);
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        s.shrink_to_fit();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "a value");
+        assert_eq!(s.capacity(), MAX_EMBEDDED_LEN);
+    }
+
+    #[test]
+    fn test_shrink_to_fit_heap() {
+        let mut s = EmbeddingString::from("a value longer than the limit");
+        s.push_str(&"!".repeat(100));
+        s.truncate(20);
+        let before = s.capacity();
+        assert!(before >= 120);
+        s.shrink_to_fit();
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "a value longer than ");
+        // std only promises a capacity close to the length
+        assert!(
+            s.capacity() >= 20 && s.capacity() < before,
+            "{}",
+            s.capacity()
+        );
+
+        let mut s = EmbeddingString::from("short");
+        s.shrink_to_fit();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "short");
+    }
+
+    #[test]
+    fn test_replace_range() {
+        let mut s = EmbeddingString::from("hello world");
+        s.replace_range(6.., "rust");
+        assert_eq!(s.as_str(), "hello rust");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+
+        // grows past the embedded size
+        s.replace_range(..5, "goodbye, cruel");
+        assert_eq!(s.as_str(), "goodbye, cruel rust");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+
+        s.replace_range(8..=14, "");
+        assert_eq!(s.as_str(), "goodbye,rust");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+
+        let mut s = EmbeddingString::from("a heap value that stays long");
+        s.replace_range(2..6, "HEAP");
+        assert_eq!(s.as_str(), "a HEAP value that stays long");
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        s.replace_range(.., "");
+        assert_eq!(s.as_str(), "");
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+
+        let mut s = EmbeddingString::from("한글");
+        s.replace_range(3.., "국");
+        assert_eq!(s.as_str(), "한국");
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 1 is not a char boundary")]
+    fn test_replace_range_not_char_boundary() {
+        EmbeddingString::from("한글").replace_range(1.., "");
+    }
+
+    #[test]
+    fn test_into_mut_round_trip() {
+        let mut s = EmbeddingStr::from("short").into_mut();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        s.push_str(" & sweet");
+        let s = s.into_immutable();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "short & sweet");
+
+        let mut s = s.into_mut();
+        s.push_str(", then longer");
+        let s = s.into_immutable();
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str(), "short & sweet, then longer");
+
+        let ptr = s.as_str().as_ptr();
+        let s = s.into_mut();
+        assert_eq!(s.mode(), EmbeddingStrMode::Boxed);
+        assert_eq!(s.as_str().as_ptr(), ptr);
+        let mut s = s.into_immutable();
+        assert_eq!(s.as_str().as_ptr(), ptr);
+        assert_eq!(s.as_str(), "short & sweet, then longer");
+
+        let mut m = s.into_mut();
+        m.truncate(5);
+        s = m.into_immutable();
+        assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+        assert_eq!(s.as_str(), "short");
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 1 is not a char boundary")]
+    fn test_truncate_not_char_boundary() {
+        EmbeddingString::from("한글").truncate(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "byte index 1 is not a char boundary")]
+    fn test_truncate_heap_not_char_boundary() {
+        EmbeddingString::from("한글 and then some more").truncate(1);
+    }
+}
@@ -0,0 +1,158 @@
+//! Allocation accounting tests using a counting global allocator.
+//!
+//! Counters are thread-local so tests running in parallel don't observe each other.
+
+use embed_str::{EmbeddingStr, EmbeddingStrMode};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+    static DEALLOCS: Cell<usize> = const { Cell::new(0) };
+    static LAST_DEALLOC: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCS.try_with(|c| c.set(c.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = DEALLOCS.try_with(|c| c.set(c.get() + 1));
+        let _ = LAST_DEALLOC.try_with(|c| c.set(ptr as usize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[derive(Debug, PartialEq)]
+struct Counts {
+    allocs: usize,
+    deallocs: usize,
+}
+
+/// Run `f` and report the allocations it made on this thread
+fn count<R>(f: impl FnOnce() -> R) -> (R, Counts) {
+    let allocs = ALLOCS.with(Cell::get);
+    let deallocs = DEALLOCS.with(Cell::get);
+    let r = f();
+    let counts = Counts {
+        allocs: ALLOCS.with(Cell::get) - allocs,
+        deallocs: DEALLOCS.with(Cell::get) - deallocs,
+    };
+    (r, counts)
+}
+
+fn last_dealloc() -> usize {
+    LAST_DEALLOC.with(Cell::get)
+}
+
+#[test]
+fn test_short_box_is_freed() {
+    let b: Box<str> = "short".into();
+    let ptr = b.as_ptr() as usize;
+    let (s, counts) = count(|| EmbeddingStr::from(b));
+    assert_eq!(
+        counts,
+        Counts {
+            allocs: 0,
+            deallocs: 1
+        }
+    );
+    assert_eq!(last_dealloc(), ptr);
+    assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+    assert_eq!(s.as_str(), "short");
+}
+
+#[test]
+fn test_short_fmt_does_not_allocate() {
+    let (s, counts) = count(|| EmbeddingStr::from(format_args!("x={}", 5)));
+    assert_eq!(
+        counts,
+        Counts {
+            allocs: 0,
+            deallocs: 0
+        }
+    );
+    assert_eq!(s.as_str(), "x=5");
+}
+
+#[test]
+fn test_short_owned_cow_only_frees() {
+    use std::borrow::Cow;
+
+    let owned = String::from("short");
+    let ptr = owned.as_ptr() as usize;
+    let (s, counts) = count(|| EmbeddingStr::from(Cow::Owned(owned)));
+    assert_eq!(
+        counts,
+        Counts {
+            allocs: 0,
+            deallocs: 1
+        }
+    );
+    assert_eq!(last_dealloc(), ptr);
+    assert_eq!(s.mode(), EmbeddingStrMode::Embedded);
+
+    let (s, counts) = count(|| EmbeddingStr::from(Cow::Borrowed("short")));
+    assert_eq!(
+        counts,
+        Counts {
+            allocs: 0,
+            deallocs: 0
+        }
+    );
+    assert_eq!(s.as_str(), "short");
+}
+
+#[test]
+fn test_from_strings_reuses_long_buffers() {
+    let v: Vec<String> = [
+        "short",
+        "something longer than 15 bytes",
+        "x",
+        "another string longer than the limit",
+    ]
+    .iter()
+    .map(|&s| String::from(s))
+    .collect();
+    let long_ptrs = [v[1].as_ptr(), v[3].as_ptr()];
+    // the exact counts depend on whether std collects in place into the input vector, so
+    // only check that the long buffers changed hands without a copy
+    let converted = EmbeddingStr::from_strings(v);
+    assert_eq!(converted[1].as_str().as_ptr(), long_ptrs[0]);
+    assert_eq!(converted[3].as_str().as_ptr(), long_ptrs[1]);
+}
+
+#[cfg(feature = "pool")]
+#[test]
+fn test_pool_reuses_buffers() {
+    let pool = embed_str::StrPool::new();
+    let lines = [
+        "2024-01-01 first log line",
+        "2024-01-01 other log line",
+        "2024-01-02 third log line",
+    ];
+
+    let (s, counts) = count(|| pool.alloc(lines[0]));
+    assert_eq!(counts.allocs, 1);
+    pool.recycle(s);
+    for line in lines.iter().cycle().take(10) {
+        let (s, counts) = count(|| pool.alloc(line));
+        assert_eq!(
+            counts,
+            Counts {
+                allocs: 0,
+                deallocs: 0
+            }
+        );
+        assert_eq!(s.as_str(), *line);
+        let ((), counts) = count(|| pool.recycle(s));
+        assert_eq!(counts.allocs, 0);
+    }
+}